@@ -0,0 +1,48 @@
+use crate::{
+    games::ataxx::{AtaxxBoard, AtaxxColor, AtaxxState, single_moves},
+    util::Bitboard,
+};
+
+use super::Eval;
+
+const PIECE_VALUE: i32 = 100;
+const MOBILITY: i32 = 6;
+const FRONTIER: i32 = -9;
+const EDGE: i32 = 7;
+const BLOCKER_NEIGHBOUR: i32 = 5;
+
+const EDGES: Bitboard<7, 7> = Bitboard::RANK_0
+    .bit_or(Bitboard::LAST_RANK)
+    .bit_or(Bitboard::FILE_A)
+    .bit_or(Bitboard::LAST_FILE);
+
+fn evaluate_color(state: &AtaxxState, color: AtaxxColor) -> i32 {
+    let pieces = state.pieces(color);
+    let empty = !state.occ() & Bitboard::ALL;
+
+    // pieces next to an empty square can be flipped by an enemy single move
+    let frontier = pieces & single_moves(empty);
+    let mobility = single_moves(pieces) & empty;
+    // pieces on the edge or next to blockers have fewer neighbours to be captured from
+    let stable_edge = pieces & EDGES;
+    let stable_blocker = pieces & single_moves(state.blockers());
+
+    let mut eval = 0;
+    eval += PIECE_VALUE * pieces.popcount() as i32;
+    eval += MOBILITY * mobility.popcount() as i32;
+    eval += FRONTIER * frontier.popcount() as i32;
+    eval += EDGE * stable_edge.popcount() as i32;
+    eval += BLOCKER_NEIGHBOUR * stable_blocker.popcount() as i32;
+    eval
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AtaxxEval {}
+
+impl Eval<AtaxxBoard> for AtaxxEval {
+    fn evaluate(&mut self, board: &AtaxxBoard) -> i32 {
+        let state = board.curr_state();
+        let stm = state.stm();
+        evaluate_color(state, stm) - evaluate_color(state, stm.flip())
+    }
+}
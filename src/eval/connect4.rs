@@ -0,0 +1,74 @@
+use crate::games::connect4::{Connect4Bitboard, Connect4Board, Connect4Color};
+
+use super::Eval;
+
+const THREAT: i32 = 12;
+const GOOD_PARITY_THREAT: i32 = 30;
+const PLAYABLE_THREAT: i32 = 8;
+const STACKED_THREAT: i32 = 60;
+const CENTER: i32 = 4;
+
+// every other row starting from first, so 0 gives rows 1, 3, 5... counting from 1
+const fn alternate_rows<const COLUMNS: u8, const ROWS: u8>(
+    first: u8,
+) -> Connect4Bitboard<COLUMNS, ROWS> {
+    let mut result = Connect4Bitboard::NONE;
+    let mut row = first;
+    while row < ROWS {
+        result = result.bit_or(Connect4Bitboard::row(row));
+        row += 2;
+    }
+    result
+}
+
+// the middle column, or both middle columns on boards with an even number of them
+const fn center<const COLUMNS: u8, const ROWS: u8>() -> Connect4Bitboard<COLUMNS, ROWS> {
+    Connect4Bitboard::column((COLUMNS - 1) / 2).bit_or(Connect4Bitboard::column(COLUMNS / 2))
+}
+
+fn evaluate_threats<const COLUMNS: u8, const ROWS: u8>(
+    threats: Connect4Bitboard<COLUMNS, ROWS>,
+    move_locations: Connect4Bitboard<COLUMNS, ROWS>,
+    color: Connect4Color,
+) -> i32 {
+    // zugzwang favours the first player on odd rows and the second player on even rows
+    let good_rows = if color == Connect4Color::Red {
+        alternate_rows(0)
+    } else {
+        alternate_rows(1)
+    };
+
+    let mut eval = 0;
+    eval += THREAT * threats.popcount() as i32;
+    eval += GOOD_PARITY_THREAT * (threats & good_rows).popcount() as i32;
+    eval += PLAYABLE_THREAT * (threats & move_locations).popcount() as i32;
+    // two threats directly on top of each other cannot both be stopped
+    eval += STACKED_THREAT * (threats & threats.south()).popcount() as i32;
+    eval
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Connect4Eval {}
+
+impl Connect4Eval {
+    // keeps heuristic scores well away from the solver's win scores
+    pub const MAX_EVAL: i32 = 500;
+}
+
+impl<const COLUMNS: u8, const ROWS: u8> Eval<Connect4Board<COLUMNS, ROWS>> for Connect4Eval {
+    fn evaluate(&mut self, board: &Connect4Board<COLUMNS, ROWS>) -> i32 {
+        let state = board.curr_state();
+        let stm = state.stm();
+        let move_locations = state.move_locations();
+
+        let mut eval = 0;
+        eval += evaluate_threats(state.our_threats(), move_locations, stm);
+        eval -= evaluate_threats(state.their_threats(), move_locations, stm.flip());
+
+        let center = center();
+        eval += CENTER * (state.pieces(stm) & center).popcount() as i32;
+        eval -= CENTER * (state.pieces(stm.flip()) & center).popcount() as i32;
+
+        eval.clamp(-Self::MAX_EVAL, Self::MAX_EVAL)
+    }
+}
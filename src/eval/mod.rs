@@ -1,9 +1,13 @@
+pub mod ataxx;
+pub mod connect4;
 pub mod three_check;
 
+pub use ataxx::AtaxxEval;
+pub use connect4::Connect4Eval;
 pub use three_check::ThreeCheckEval;
 
 use crate::games::board::Board;
 
 pub trait Eval<B: Board> {
-    fn evaluate(board: &B) -> i32;
+    fn evaluate(&mut self, board: &B) -> i32;
 }
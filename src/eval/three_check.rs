@@ -1,5 +1,9 @@
-use crate::games::three_check::{
-    Bitboard, Color, Piece, PieceType, ThreeCheckBoard, ThreeCheckState, attacks,
+use crate::{
+    games::three_check::{
+        Bitboard, Color, Piece, PieceType, Square, ThreeCheckBoard, ThreeCheckState, Variant,
+        attacks,
+    },
+    search::tt::TT,
 };
 
 use super::Eval;
@@ -34,6 +38,12 @@ impl EvalData {
         result.attacked[Color::Black as usize] |= b_pawn_atks;
         result.attacked_by[Color::Black as usize][PieceType::Pawn as usize] |= b_pawn_atks;
 
+        // king attacks stay out of attacked, which decides the safe check squares
+        result.attacked_by[Color::White as usize][PieceType::King as usize] =
+            attacks::king_attacks(state.king_sq(Color::White));
+        result.attacked_by[Color::Black as usize][PieceType::King as usize] =
+            attacks::king_attacks(state.king_sq(Color::Black));
+
         result.mobility_area[Color::White as usize] = !b_pawn_atks;
         result.mobility_area[Color::Black as usize] = !w_pawn_atks;
 
@@ -49,6 +59,11 @@ impl EvalData {
 
         result
     }
+
+    // every square color attacks, including the ones only the king attacks
+    fn all_attacks(&self, color: Color) -> Bitboard {
+        self.attacked[color as usize] | self.attacked_by[color as usize][PieceType::King as usize]
+    }
 }
 
 const PST_RANK: [i32; 48] = [
@@ -173,6 +188,201 @@ fn evaluate_piece(
     eval
 }
 
+const THREAT_BY_PAWN: [i32; 6] = [0, 52, 55, 74, 68, 0];
+const THREAT_BY_MINOR: [i32; 6] = [5, 23, 26, 44, 41, 0];
+const HANGING: i32 = 32;
+const PAWN_PUSH_THREAT: i32 = 17;
+const RESTRICTED_SQUARE: i32 = 3;
+
+fn threat_score(state: &ThreeCheckState, mut targets: Bitboard, values: &[i32; 6]) -> i32 {
+    let mut eval = 0;
+    while targets.any() {
+        let sq = targets.poplsb();
+        eval += values[state.piece_at(sq).unwrap().piece_type() as usize];
+    }
+    eval
+}
+
+// must run after evaluate_pieces for both sides so the attack maps are complete
+fn evaluate_threats(state: &ThreeCheckState, eval_data: &EvalData, color: Color) -> i32 {
+    let us = color as usize;
+    let them = color.flip() as usize;
+
+    let their_pieces = state.colors(color.flip()) & !state.pieces(PieceType::King);
+    let their_non_pawns = their_pieces & !state.pieces(PieceType::Pawn);
+    let our_attacks = eval_data.all_attacks(color);
+    let their_attacks = eval_data.all_attacks(color.flip());
+    let undefended = their_pieces & !their_attacks;
+
+    let by_pawn = eval_data.attacked_by[us][PieceType::Pawn as usize];
+    let by_minor = eval_data.attacked_by[us][PieceType::Knight as usize]
+        | eval_data.attacked_by[us][PieceType::Bishop as usize];
+
+    let mut eval = 0;
+    eval += threat_score(state, their_non_pawns & by_pawn, &THREAT_BY_PAWN);
+    eval += threat_score(
+        state,
+        (their_non_pawns | undefended) & by_minor,
+        &THREAT_BY_MINOR,
+    );
+    eval += HANGING * (undefended & our_attacks).popcount() as i32;
+
+    let third_rank = if color == Color::White {
+        Bitboard::rank(2)
+    } else {
+        Bitboard::rank(5)
+    };
+    let safe = !their_attacks | our_attacks;
+    let our_pawns = state.colored_pieces(Piece::new(color, PieceType::Pawn));
+    let mut pushes = attacks::pawn_pushes_bb(color, our_pawns) & !state.occ();
+    pushes |= attacks::pawn_pushes_bb(color, pushes & third_rank) & !state.occ();
+    pushes &= !eval_data.attacked_by[them][PieceType::Pawn as usize] & safe;
+    let push_threats = attacks::pawn_attacks_bb(color, pushes) & their_non_pawns;
+    eval += PAWN_PUSH_THREAT * push_threats.popcount() as i32;
+
+    // contested squares that the opponent does not hold with a pawn
+    let restricted =
+        their_attacks & !eval_data.attacked_by[them][PieceType::Pawn as usize] & our_attacks;
+    eval += RESTRICTED_SQUARE * restricted.popcount() as i32;
+
+    eval
+}
+
+fn north_fill(bb: Bitboard) -> Bitboard {
+    let mut value = bb.value();
+    value |= value << 8;
+    value |= value << 16;
+    value |= value << 32;
+    Bitboard::from_raw(value)
+}
+
+fn south_fill(bb: Bitboard) -> Bitboard {
+    let mut value = bb.value();
+    value |= value >> 8;
+    value |= value >> 16;
+    value |= value >> 32;
+    Bitboard::from_raw(value)
+}
+
+// all squares strictly in front of the pieces in bb from color's point of view
+fn front_span(color: Color, bb: Bitboard) -> Bitboard {
+    if color == Color::White {
+        north_fill(bb.north())
+    } else {
+        south_fill(bb.south())
+    }
+}
+
+fn relative_rank(color: Color, sq: Square) -> usize {
+    if color == Color::White {
+        sq.rank() as usize
+    } else {
+        7 - sq.rank() as usize
+    }
+}
+
+const PASSED_PAWN: [i32; 8] = [0, 8, 12, 22, 40, 70, 115, 0];
+const CONNECTED_PAWN: [i32; 8] = [0, 4, 7, 10, 18, 32, 55, 0];
+const DOUBLED_PAWN: i32 = -14;
+const ISOLATED_PAWN: i32 = -11;
+const BACKWARD_PAWN: i32 = -9;
+
+// everything here only depends on pawn placement, so the result can be cached by pawn key
+fn evaluate_pawns(state: &ThreeCheckState, color: Color) -> i32 {
+    let our_pawns = state.colored_pieces(Piece::new(color, PieceType::Pawn));
+    let their_pawns = state.colored_pieces(Piece::new(color.flip(), PieceType::Pawn));
+    let their_pawn_attacks = attacks::pawn_attacks_bb(color.flip(), their_pawns);
+
+    let their_span = front_span(color.flip(), their_pawns);
+    let blocked = their_span | their_span.east() | their_span.west();
+
+    let our_files = north_fill(our_pawns) | south_fill(our_pawns);
+    let neighbours = our_files.east() | our_files.west();
+
+    let supported = our_pawns & attacks::pawn_attacks_bb(color, our_pawns);
+    let phalanx = our_pawns & (our_pawns.east() | our_pawns.west());
+    let doubled = our_pawns & front_span(color.flip(), our_pawns);
+
+    let mut eval = 0;
+    eval += DOUBLED_PAWN * doubled.popcount() as i32;
+    eval += ISOLATED_PAWN * (our_pawns & !neighbours).popcount() as i32;
+
+    let mut bb = our_pawns;
+    while bb.any() {
+        let sq = bb.poplsb();
+        let sq_bb = Bitboard::from_square(sq);
+        let rank = relative_rank(color, sq);
+
+        if (sq_bb & blocked).empty() {
+            eval += PASSED_PAWN[rank];
+        }
+
+        if (sq_bb & (supported | phalanx)).any() {
+            eval += CONNECTED_PAWN[rank];
+            continue;
+        }
+
+        // no friendly pawn beside or behind on the adjacent files can ever support it,
+        // and advancing it runs into an enemy pawn attack
+        let file = Bitboard::file(sq.file());
+        let support_span = !front_span(color, sq_bb) & (file.east() | file.west());
+        let stop = attacks::pawn_pushes_bb(color, sq_bb);
+        if (support_span & our_pawns).empty() && (stop & their_pawn_attacks).any() {
+            eval += BACKWARD_PAWN;
+        }
+    }
+
+    eval
+}
+
+const PAWN_SHIELD: [i32; 3] = [-22, 24, 11];
+const PAWN_STORM: [i32; 5] = [0, 0, -24, -14, -6];
+
+fn evaluate_king_pawns(state: &ThreeCheckState, color: Color) -> i32 {
+    let king_sq = state.king_sq(color);
+    let our_pawns = state.colored_pieces(Piece::new(color, PieceType::Pawn));
+    let their_pawns = state.colored_pieces(Piece::new(color.flip(), PieceType::Pawn));
+    let king_rank = relative_rank(color, king_sq);
+    let in_front = front_span(color, Bitboard::from_square(king_sq));
+    let in_front = in_front | in_front.east() | in_front.west();
+
+    let mut eval = 0;
+    for file in king_sq.file().saturating_sub(1)..=(king_sq.file() + 1).min(7) {
+        let file_bb = Bitboard::file(file) & in_front;
+
+        let ours = our_pawns & file_bb;
+        let shield_dist = if ours.empty() {
+            0
+        } else {
+            let closest = if color == Color::White {
+                ours.lsb()
+            } else {
+                ours.msb()
+            };
+            relative_rank(color, closest) - king_rank
+        };
+        if shield_dist <= 2 {
+            eval += PAWN_SHIELD[shield_dist];
+        }
+
+        let theirs = their_pawns & file_bb;
+        if theirs.any() {
+            let closest = if color == Color::White {
+                theirs.lsb()
+            } else {
+                theirs.msb()
+            };
+            let storm_dist = relative_rank(color, closest) - king_rank;
+            if storm_dist < PAWN_STORM.len() {
+                eval += PAWN_STORM[storm_dist];
+            }
+        }
+    }
+
+    // pawns in front of the king block checks, which matters more the closer we are to losing
+    eval * (2 + checks_received(state, color)) / 2
+}
+
 fn evaluate_king(state: &ThreeCheckState, eval_data: &EvalData, color: Color) -> i32 {
     let their_king = state.king_sq(color.flip());
 
@@ -190,23 +400,125 @@ fn evaluate_king(state: &ThreeCheckState, eval_data: &EvalData, color: Color) ->
     let all_checks = knight_checks | bishop_checks | rook_checks | queen_checks;
 
     let mut eval = 0;
-    eval += 50 * (knight_checks & safe).popcount() as i32;
-    eval += 50 * (bishop_checks & safe).popcount() as i32;
-    eval += 70 * (rook_checks & safe).popcount() as i32;
-    eval += 90 * (queen_checks & safe).popcount() as i32;
-    eval += 40 * (all_checks & !safe).popcount() as i32;
+    // checks only score when they count towards the check limit
+    if state.check_limit().is_some() {
+        eval += 50 * (knight_checks & safe).popcount() as i32;
+        eval += 50 * (bishop_checks & safe).popcount() as i32;
+        eval += 70 * (rook_checks & safe).popcount() as i32;
+        eval += 90 * (queen_checks & safe).popcount() as i32;
+        eval += 40 * (all_checks & !safe).popcount() as i32;
+    }
     eval += eval_data.attack_weight[color as usize] * eval_data.attacker_count[color as usize];
-    eval * (2 + state.check_count(color.flip()) as i32) / 2
+    eval * (2 + checks_received(state, color.flip())) / 2
+}
+
+// king of the hill, indexed by the king's distance to the nearest centre square
+const HILL_DISTANCE: [i32; 4] = [0, 90, 35, 0];
+const HILL_PATH_CLEAR: i32 = 60;
+
+fn evaluate_hill(state: &ThreeCheckState, eval_data: &EvalData, color: Color) -> i32 {
+    let king_sq = state.king_sq(color);
+    let (rank, file) = (king_sq.rank() as i32, king_sq.file() as i32);
+    // the hill spans ranks and files 3 and 4
+    let rank_dist = (3 - rank).max(rank - 4).max(0);
+    let file_dist = (3 - file).max(file - 4).max(0);
+    let dist = rank_dist.max(file_dist) as usize;
+
+    let mut eval = HILL_DISTANCE[dist];
+    // a hill square next to the king that we can step onto safely
+    let reachable = attacks::king_attacks(king_sq)
+        & ThreeCheckState::HILL
+        & !state.colors(color)
+        & !eval_data.all_attacks(color.flip());
+    if reachable.any() {
+        eval += HILL_PATH_CLEAR;
+    }
+    eval
+}
+
+// checks already received on the three check scale, so n-check positions with the same
+// number of checks remaining are evaluated alike. Always 0 when checks are disabled
+fn checks_received(state: &ThreeCheckState, color: Color) -> i32 {
+    match state.checks_remaining(color) {
+        Some(remaining) => (3 - remaining as i32).clamp(0, 2),
+        None => 0,
+    }
+}
+
+// antichess is won by running out of pieces, so material counts against you.
+// kings and rooks are the hardest pieces to give away in the endgame
+const ANTICHESS_VALUES: [i32; 6] = [-60, -90, -70, -110, -80, -100];
+// the opponent has to take these
+const ANTICHESS_EN_PRISE: i32 = 25;
+const ANTICHESS_MOBILITY: i32 = 3;
+
+fn evaluate_antichess(state: &ThreeCheckState, color: Color) -> i32 {
+    let mut eval = 0;
+    let occ = state.occ();
+    let mut pieces = state.colors(color);
+    while pieces.any() {
+        let sq = pieces.poplsb();
+        let pt = state.piece_at(sq).unwrap().piece_type();
+        eval += ANTICHESS_VALUES[pt as usize];
+        if (state.all_attackers_to(sq, occ) & state.colors(color.flip())).any() {
+            eval += ANTICHESS_EN_PRISE;
+        }
+        // having quiet moves to spare avoids being forced into bad captures
+        let attacks = match pt {
+            PieceType::Pawn => Bitboard::NONE,
+            PieceType::Knight => attacks::knight_attacks(sq),
+            PieceType::Bishop => attacks::bishop_attacks(sq, occ),
+            PieceType::Rook => attacks::rook_attacks(sq, occ),
+            PieceType::Queen => attacks::queen_attacks(sq, occ),
+            PieceType::King => attacks::king_attacks(sq),
+        };
+        eval += ANTICHESS_MOBILITY * (attacks & !occ).popcount() as i32;
+    }
+    eval
 }
 
 #[derive(Debug, Default, Clone, Copy)]
-pub struct ThreeCheckEval {}
+struct PawnEntry {
+    eval: i32,
+}
+
+pub struct ThreeCheckEval {
+    pawn_table: TT<PawnEntry>,
+}
+
+impl ThreeCheckEval {
+    pub fn new() -> Self {
+        Self {
+            pawn_table: TT::new(1),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pawn_table.clear();
+    }
+
+    fn probe_pawns(&mut self, state: &ThreeCheckState) -> i32 {
+        let key = state.pawn_key().value();
+        if let Some(entry) = self.pawn_table.probe(key) {
+            return entry.eval;
+        }
+
+        let eval = evaluate_pawns(state, Color::White) - evaluate_pawns(state, Color::Black);
+        self.pawn_table.store(key, PawnEntry { eval: eval });
+        eval
+    }
+}
 
 impl Eval<ThreeCheckBoard> for ThreeCheckEval {
-    fn evaluate(board: &ThreeCheckBoard) -> i32 {
+    fn evaluate(&mut self, board: &ThreeCheckBoard) -> i32 {
         const CHECK_PENALTY: [i32; 3] = [0, -200, -750];
 
         let state = board.curr_state();
+        if state.variant() == Variant::Antichess {
+            return evaluate_antichess(state, state.stm())
+                - evaluate_antichess(state, state.stm().flip());
+        }
+
         let mut eval = 0;
         let mut eval_data = EvalData::new(state);
 
@@ -215,9 +527,17 @@ impl Eval<ThreeCheckBoard> for ThreeCheckEval {
             - evaluate_pieces(state, &mut eval_data, Color::Black);
         eval += evaluate_king(state, &eval_data, Color::White)
             - evaluate_king(state, &eval_data, Color::Black);
+        eval += evaluate_threats(state, &eval_data, Color::White)
+            - evaluate_threats(state, &eval_data, Color::Black);
+        eval += self.probe_pawns(state);
+        eval += evaluate_king_pawns(state, Color::White) - evaluate_king_pawns(state, Color::Black);
+        if state.variant() == Variant::KingOfTheHill {
+            eval += evaluate_hill(state, &eval_data, Color::White)
+                - evaluate_hill(state, &eval_data, Color::Black);
+        }
 
-        eval += CHECK_PENALTY[state.check_count(Color::White) as usize]
-            - CHECK_PENALTY[state.check_count(Color::Black) as usize];
+        eval += CHECK_PENALTY[checks_received(state, Color::White) as usize]
+            - CHECK_PENALTY[checks_received(state, Color::Black) as usize];
 
         if state.stm() == Color::White {
             eval
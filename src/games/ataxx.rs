@@ -4,8 +4,8 @@ use std::collections::HashMap;
 use arrayvec::ArrayVec;
 
 use crate::{
-    games::board::{CopyMakeBoard, CopyMakeWrapper, GameResult},
-    util::{Bitboard, Square, parse_fen_pieces},
+    games::board::{BoardIndex, CopyMakeBoard, CopyMakeWrapper, GameResult, HashedBoard},
+    util::{Bitboard, Square, hash_combine, murmur_hash3, parse_fen_pieces},
 };
 
 pub type AtaxxSquare = Square<7, 7>;
@@ -56,6 +56,18 @@ impl AtaxxMove {
     }
 }
 
+impl fmt::Display for AtaxxMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_null() {
+            write!(f, "0000")
+        } else if self.is_single() {
+            write!(f, "{}", self.to_sq())
+        } else {
+            write!(f, "{}{}", self.from_sq(), self.to_sq())
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AtaxxColor {
     Black,
@@ -78,7 +90,7 @@ pub enum AtaxxPiece {
     Blocker,
 }
 
-const fn single_moves(pieces: Bitboard<7, 7>) -> Bitboard<7, 7> {
+pub const fn single_moves(pieces: Bitboard<7, 7>) -> Bitboard<7, 7> {
     let we = pieces.west().bit_or(pieces.east());
     let ns = pieces.north().bit_or(pieces.south());
     we.bit_or(ns.west()).bit_or(ns.east()).bit_or(ns)
@@ -120,6 +132,10 @@ const DOUBLE_MOVES: [Bitboard<7, 7>; 49] = {
     result
 };
 
+pub fn adjacent_squares(sq: AtaxxSquare) -> Bitboard<7, 7> {
+    ADJACENT_SQUARES[sq.value() as usize]
+}
+
 #[derive(Debug, Clone)]
 pub struct AtaxxState {
     pieces: [Bitboard<7, 7>; 2],
@@ -137,9 +153,39 @@ impl AtaxxState {
         &mut self.pieces[c as usize]
     }
 
+    pub fn blockers(&self) -> Bitboard<7, 7> {
+        self.blockers
+    }
+
     pub fn occ(&self) -> Bitboard<7, 7> {
         self.pieces[0] | self.pieces[1] | self.blockers
     }
+
+    pub fn stm(&self) -> AtaxxColor {
+        self.stm
+    }
+
+    pub fn half_move_clock(&self) -> u8 {
+        self.half_move_clock
+    }
+
+    // number of opponent pieces a move would flip
+    pub fn captures(&self, mv: AtaxxMove) -> u32 {
+        if mv.is_null() {
+            return 0;
+        }
+        (self.pieces(self.stm.flip()) & adjacent_squares(mv.to_sq())).popcount()
+    }
+
+    pub fn key(&self) -> u64 {
+        let key = hash_combine(
+            murmur_hash3(self.pieces(AtaxxColor::Black).value()),
+            murmur_hash3(self.pieces(AtaxxColor::White).value()),
+        );
+        // positions with different blocker layouts must not share tt entries
+        let key = hash_combine(key, murmur_hash3(self.blockers.value()));
+        hash_combine(key, self.stm as u64)
+    }
 }
 
 impl CopyMakeBoard for AtaxxState {
@@ -239,7 +285,9 @@ impl CopyMakeBoard for AtaxxState {
         if self.pieces(self.stm.flip()).empty() {
             return GameResult::WIN;
         }
-        let possible_moves = !self.occ() & single_moves(single_moves(self.occ()));
+        // blockers never move, so only squares near a piece can still be filled
+        let possible_moves =
+            !self.occ() & single_moves(single_moves(self.pieces[0] | self.pieces[1]));
         if possible_moves.empty() {
             let score = self.pieces(self.stm).popcount() as i32
                 - self.pieces(self.stm.flip()).popcount() as i32;
@@ -333,3 +381,57 @@ impl fmt::Display for AtaxxState {
 }
 
 pub type AtaxxBoard = CopyMakeWrapper<AtaxxState>;
+
+impl HashedBoard for AtaxxBoard {
+    // the clock decides draws, so it has to be part of the key
+    fn hash_key(&self) -> u64 {
+        hash_combine(
+            self.curr_state().key(),
+            self.curr_state().half_move_clock() as u64,
+        )
+    }
+}
+
+// only practical when blockers leave a handful of open squares. those are numbered in base 3,
+// followed by the side to move and the half move clock
+pub struct AtaxxIndex {
+    blockers: Bitboard<7, 7>,
+    squares: Vec<AtaxxSquare>,
+}
+
+impl AtaxxIndex {
+    pub fn new(blockers: Bitboard<7, 7>) -> Self {
+        let mut open = Bitboard::ALL & !blockers;
+        let mut squares = Vec::new();
+        while open.any() {
+            squares.push(open.poplsb());
+        }
+        Self { blockers, squares }
+    }
+}
+
+impl BoardIndex<AtaxxBoard> for AtaxxIndex {
+    fn size(&self) -> usize {
+        3usize.pow(self.squares.len() as u32) * 2 * 101
+    }
+
+    fn index(&self, board: &AtaxxBoard) -> Option<usize> {
+        let state = board.curr_state();
+        if state.blockers != self.blockers || state.half_move_clock > 100 {
+            return None;
+        }
+        let mut index = 0;
+        for sq in self.squares.iter().rev() {
+            let piece = if state.pieces(AtaxxColor::Black).has(*sq) {
+                1
+            } else if state.pieces(AtaxxColor::White).has(*sq) {
+                2
+            } else {
+                0
+            };
+            index = 3 * index + piece;
+        }
+        index = 2 * index + state.stm as usize;
+        Some(101 * index + state.half_move_clock as usize)
+    }
+}
@@ -28,6 +28,20 @@ pub trait Board: Sized + Clone {
     fn unmake_move(&mut self);
 }
 
+// boards with a key covering everything gen_moves and game_result depend on,
+// so results can be cached per position
+pub trait HashedBoard: Board {
+    fn hash_key(&self) -> u64;
+}
+
+// numbers positions from 0 to size - 1 for tables that store every position of a game.
+// a game can have several, like ataxx tables for different blocker layouts
+pub trait BoardIndex<B: Board> {
+    fn size(&self) -> usize;
+    // None for positions the table does not cover
+    fn index(&self, board: &B) -> Option<usize>;
+}
+
 pub trait CopyMakeBoard: Sized + Clone {
     type Move: Debug + Copy + Clone + PartialEq + Eq;
     type Square: Copy + Clone + PartialEq + Eq + PartialEq + Ord;
@@ -58,6 +72,10 @@ impl<T> CopyMakeWrapper<T>
 where
     T: CopyMakeBoard,
 {
+    pub fn from_state(state: T) -> Self {
+        Self { stack: vec![state] }
+    }
+
     pub fn curr_state(&self) -> &T {
         self.stack.last().unwrap()
     }
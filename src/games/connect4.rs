@@ -5,23 +5,51 @@ use core::fmt;
 use std::collections::HashMap;
 
 use arrayvec::ArrayVec;
-use bitboard::Connect4Bitboard;
-use square::Connect4Square;
+pub use bitboard::Connect4Bitboard;
+pub use square::Connect4Square;
 
 use crate::{
-    games::board::{CopyMakeBoard, CopyMakeWrapper, GameResult},
-    util::{Square, murmur_hash3, parse_fen_pieces},
+    games::board::{BoardIndex, CopyMakeBoard, CopyMakeWrapper, GameResult, HashedBoard},
+    util::{murmur_hash3, parse_fen_pieces},
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Connect4Move(Connect4Square);
+pub struct Connect4Move<const COLUMNS: u8 = 7, const ROWS: u8 = 6>(Connect4Square<COLUMNS, ROWS>);
 
-impl Connect4Move {
-    pub fn sq(&self) -> Connect4Square {
+impl<const COLUMNS: u8, const ROWS: u8> Connect4Move<COLUMNS, ROWS> {
+    // nothing is ever played on a sentinel square, so one stands in for no move
+    pub fn null() -> Self {
+        Self(Connect4Square::from_row_column(ROWS, 0))
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0.row() == ROWS
+    }
+
+    pub fn sq(&self) -> Connect4Square<COLUMNS, ROWS> {
         self.0
     }
+
+    // the same move on the board mirrored left to right
+    pub fn flip_horizontal(&self) -> Self {
+        Self(self.0.flip_horizontal())
+    }
 }
 
+// moves are written as the column they are played in, counting from 1, and the null move as 0
+impl<const COLUMNS: u8, const ROWS: u8> fmt::Display for Connect4Move<COLUMNS, ROWS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_null() {
+            write!(f, "0")
+        } else {
+            write!(f, "{}", self.0.column() + 1)
+        }
+    }
+}
+
+// boards need at least 4 rows for vertical lines, so no more than 12 columns fit in 64 bits
+pub const MAX_COLUMNS: usize = 12;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Connect4Color {
     Red,
@@ -38,13 +66,19 @@ impl Connect4Color {
 }
 
 #[derive(Debug, Clone)]
-pub struct Connect4State {
-    pieces: [Connect4Bitboard; 2],
+pub struct Connect4State<const COLUMNS: u8 = 7, const ROWS: u8 = 6> {
+    pieces: [Connect4Bitboard<COLUMNS, ROWS>; 2],
     stm: Connect4Color,
 }
 
-impl Connect4State {
-    pub fn pieces(&self, c: Connect4Color) -> Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> Connect4State<COLUMNS, ROWS> {
+    // bit distances to the next square in a line: up, right and both diagonals
+    const UP: u8 = 1;
+    const RIGHT: u8 = ROWS + 1;
+    const DOWN_RIGHT: u8 = ROWS;
+    const UP_RIGHT: u8 = ROWS + 2;
+
+    pub fn pieces(&self, c: Connect4Color) -> Connect4Bitboard<COLUMNS, ROWS> {
         self.pieces[c as usize]
     }
 
@@ -52,105 +86,138 @@ impl Connect4State {
         self.stm
     }
 
-    pub fn occ(&self) -> Connect4Bitboard {
+    pub fn occ(&self) -> Connect4Bitboard<COLUMNS, ROWS> {
         self.pieces[0] | self.pieces[1]
     }
 
-    pub fn above_pieces(&self) -> Connect4Bitboard {
-        Connect4Bitboard::from_raw(self.occ().value() + Connect4Bitboard::row(0).value())
+    pub fn above_pieces(&self) -> Connect4Bitboard<COLUMNS, ROWS> {
+        Connect4Bitboard::from_raw(
+            self.occ().value() + Connect4Bitboard::<COLUMNS, ROWS>::row(0).value(),
+        )
     }
 
-    pub fn move_locations(&self) -> Connect4Bitboard {
+    pub fn move_locations(&self) -> Connect4Bitboard<COLUMNS, ROWS> {
         self.above_pieces() & Connect4Bitboard::VALID
     }
 
-    pub fn move_locations_after(&self, mv: Connect4Move) -> Connect4Bitboard {
+    pub fn empty_squares(&self) -> u32 {
+        (Connect4Bitboard::VALID & !self.occ()).popcount()
+    }
+
+    pub fn move_locations_after(
+        &self,
+        mv: Connect4Move<COLUMNS, ROWS>,
+    ) -> Connect4Bitboard<COLUMNS, ROWS> {
         let mut occ = self.occ();
         occ.set(mv.sq());
-        let above_pieces =
-            Connect4Bitboard::from_raw(occ.value() + Connect4Bitboard::row(0).value());
+        let above_pieces = Connect4Bitboard::from_raw(
+            occ.value() + Connect4Bitboard::<COLUMNS, ROWS>::row(0).value(),
+        );
         above_pieces & Connect4Bitboard::VALID
     }
 
     // a perfect hash is possible but I'm too lazy to do that. This should be good enough
     pub fn key(&self) -> u64 {
-        murmur_hash3((self.above_pieces() | self.pieces(Connect4Color::Red)).value())
+        murmur_hash3(self.position_code())
+    }
+
+    pub fn mirrored_key(&self) -> u64 {
+        let code = Connect4Bitboard::<COLUMNS, ROWS>::from_raw(self.position_code());
+        murmur_hash3(code.flip_horizontal().value())
+    }
+
+    // the smaller of the key and the mirrored key, so a position and its mirror image share a
+    // key. also returns whether the mirrored key was taken
+    pub fn canonical_key(&self) -> (u64, bool) {
+        let key = self.key();
+        let mirrored_key = self.mirrored_key();
+        if mirrored_key < key {
+            (mirrored_key, true)
+        } else {
+            (key, false)
+        }
+    }
+
+    // unique to the position, the bit above the top piece of each column marks its height
+    pub fn position_code(&self) -> u64 {
+        (self.above_pieces() | self.pieces(Connect4Color::Red)).value()
     }
 
-    pub fn our_threats(&self) -> Connect4Bitboard {
+    // the same position mirrored left to right has the same value, so it is stored once
+    pub fn symmetric_code(&self) -> u64 {
+        let code = Connect4Bitboard::<COLUMNS, ROWS>::from_raw(self.position_code());
+        self.position_code().min(code.flip_horizontal().value())
+    }
+
+    pub fn our_threats(&self) -> Connect4Bitboard<COLUMNS, ROWS> {
         Self::compute_threats(self.pieces(self.stm()), self.occ())
     }
 
-    pub fn their_threats(&self) -> Connect4Bitboard {
+    pub fn their_threats(&self) -> Connect4Bitboard<COLUMNS, ROWS> {
         Self::compute_threats(self.pieces(self.stm().flip()), self.occ())
     }
 
-    pub fn our_threats_after(&self, mv: Connect4Move) -> Connect4Bitboard {
+    pub fn our_threats_after(
+        &self,
+        mv: Connect4Move<COLUMNS, ROWS>,
+    ) -> Connect4Bitboard<COLUMNS, ROWS> {
         let mut pieces = self.pieces(self.stm());
         pieces.set(mv.sq());
         Self::compute_threats(pieces, self.occ())
     }
 
-    fn compute_threats(pieces: Connect4Bitboard, occ: Connect4Bitboard) -> Connect4Bitboard {
+    // empty squares that would complete a line of 4 for pieces. lines cannot wrap around to the
+    // next column since the sentinel bits between columns are always empty
+    fn compute_threats(
+        pieces: Connect4Bitboard<COLUMNS, ROWS>,
+        occ: Connect4Bitboard<COLUMNS, ROWS>,
+    ) -> Connect4Bitboard<COLUMNS, ROWS> {
         let pieces = pieces.value();
-        let vertical = (pieces << 1) & (pieces << 2) & (pieces << 3);
-
-        let mut tmp = (pieces << 7) & (pieces << 2 * 7);
-        let mut horizontal = tmp & (pieces << 3 * 7);
-        horizontal |= tmp & (pieces >> 7);
-        tmp = (pieces >> 7) & (pieces >> 2 * 7);
-        horizontal |= tmp & (pieces >> 3 * 7);
-        horizontal |= tmp & (pieces << 7);
-
-        tmp = (pieces << 6) & (pieces << 2 * 6);
-        let mut diag1 = tmp & (pieces << 3 * 6);
-        diag1 |= tmp & (pieces >> 6);
-        tmp = (pieces >> 6) & (pieces >> 2 * 6);
-        diag1 |= tmp & (pieces >> 3 * 6);
-        diag1 |= tmp & (pieces << 6);
-
-        tmp = (pieces << 8) & (pieces << 2 * 8);
-        let mut diag2 = tmp & (pieces << 3 * 8);
-        diag2 |= tmp & (pieces >> 8);
-        tmp = (pieces >> 8) & (pieces >> 2 * 8);
-        diag2 |= tmp & (pieces >> 3 * 8);
-        diag2 |= tmp & (pieces << 8);
-
-        let result = (vertical | horizontal | diag1 | diag2) & Connect4Bitboard::VALID.value();
+        let vertical =
+            (pieces << Self::UP) & (pieces << (2 * Self::UP)) & (pieces << (3 * Self::UP));
+
+        let mut threats = vertical;
+        for shift in [Self::RIGHT, Self::DOWN_RIGHT, Self::UP_RIGHT] {
+            let mut tmp = (pieces << shift) & (pieces << (2 * shift));
+            threats |= tmp & (pieces << (3 * shift));
+            threats |= tmp & (pieces >> shift);
+            tmp = (pieces >> shift) & (pieces >> (2 * shift));
+            threats |= tmp & (pieces >> (3 * shift));
+            threats |= tmp & (pieces << shift);
+        }
+
+        let result = threats & Connect4Bitboard::<COLUMNS, ROWS>::VALID.value();
         Connect4Bitboard::from_raw(result) & !occ
     }
 
     fn is_loss(&self) -> bool {
         let pieces = self.pieces(self.stm.flip()).value();
-        let m = pieces & (pieces >> 7);
-        if (m & (m >> 14)) != 0 {
-            return true;
-        }
-
-        let m = pieces & (pieces >> 1);
-        if (m & (m >> 2)) != 0 {
-            return true;
-        }
-
-        let m = pieces & (pieces >> 6);
-        if (m & (m >> 12)) != 0 {
-            return true;
-        }
-
-        let m = pieces & (pieces >> 8);
-        if (m & (m >> 16)) != 0 {
-            return true;
+        for shift in [Self::RIGHT, Self::UP, Self::DOWN_RIGHT, Self::UP_RIGHT] {
+            let m = pieces & (pieces >> shift);
+            if (m & (m >> (2 * shift))) != 0 {
+                return true;
+            }
         }
         false
     }
+
+    // an empty board of this size
+    pub fn startpos_fen() -> String {
+        let row = if COLUMNS <= 9 {
+            COLUMNS.to_string()
+        } else {
+            format!("9{}", COLUMNS - 9)
+        };
+        format!("{} r", vec![row; ROWS as usize].join("/"))
+    }
 }
 
-impl CopyMakeBoard for Connect4State {
+impl<const COLUMNS: u8, const ROWS: u8> CopyMakeBoard for Connect4State<COLUMNS, ROWS> {
     type Color = Connect4Color;
     type Piece = Connect4Color;
-    type Square = Connect4Square;
-    type Move = Connect4Move;
-    type MoveList = ArrayVec<Connect4Move, 7>;
+    type Square = Connect4Square<COLUMNS, ROWS>;
+    type Move = Connect4Move<COLUMNS, ROWS>;
+    type MoveList = ArrayVec<Connect4Move<COLUMNS, ROWS>, MAX_COLUMNS>;
 
     fn from_fen(fen: &str) -> Option<Self> {
         let mut board = Self {
@@ -165,15 +232,13 @@ impl CopyMakeBoard for Connect4State {
 
         let result = parse_fen_pieces(
             |sq: i32, piece: Connect4Color| {
-                let conventional_sq = Square::<7, 6>::from_raw(sq as u16);
-                board.pieces[piece as usize].set(Connect4Square::from_row_column(
-                    conventional_sq.rank(),
-                    conventional_sq.file(),
-                ));
+                let row = (sq / COLUMNS as i32) as u8;
+                let column = (sq % COLUMNS as i32) as u8;
+                board.pieces[piece as usize].set(Connect4Square::from_row_column(row, column));
             },
             parts[0],
-            7,
-            6,
+            COLUMNS,
+            ROWS,
             HashMap::from([('r', Connect4Color::Red), ('y', Connect4Color::Yellow)]),
         );
         // todo: add better error handling
@@ -194,7 +259,7 @@ impl CopyMakeBoard for Connect4State {
     }
 
     fn startpos() -> Self {
-        Self::from_fen("7/7/7/7/7/7 r").unwrap()
+        Self::from_fen(&Self::startpos_fen()).unwrap()
     }
 
     fn piece_on(&self, sq: Self::Square) -> Option<Self::Piece> {
@@ -234,25 +299,55 @@ impl CopyMakeBoard for Connect4State {
     }
 }
 
-impl fmt::Display for Connect4State {
+impl<const COLUMNS: u8, const ROWS: u8> fmt::Display for Connect4State<COLUMNS, ROWS> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "---------\n")?;
-        for row in (0..6).rev() {
+        let border = "-".repeat(COLUMNS as usize + 2);
+        writeln!(f, "{}", border)?;
+        for row in (0..ROWS).rev() {
             write!(f, "|")?;
-            for column in 0..7 {
+            for column in 0..COLUMNS {
                 match self.piece_on(Connect4Square::from_row_column(row, column)) {
                     Some(Connect4Color::Red) => write!(f, "r")?,
                     Some(Connect4Color::Yellow) => write!(f, "y")?,
                     None => write!(f, ".")?,
                 }
             }
-            write!(f, "|\n")?;
+            writeln!(f, "|")?;
         }
-        write!(f, "---------\n")?;
+        writeln!(f, "{}", border)?;
         write!(f, "stm: {:?}", self.stm)?;
 
         Ok(())
     }
 }
 
-pub type Connect4Board = CopyMakeWrapper<Connect4State>;
+pub type Connect4Board<const COLUMNS: u8 = 7, const ROWS: u8 = 6> =
+    CopyMakeWrapper<Connect4State<COLUMNS, ROWS>>;
+
+impl<const COLUMNS: u8, const ROWS: u8> HashedBoard for Connect4Board<COLUMNS, ROWS> {
+    fn hash_key(&self) -> u64 {
+        self.curr_state().key()
+    }
+}
+
+// the position code already marks the height of every column, so it is used as is. that takes
+// a bit for every square and sentinel, which only fits in memory for small boards like 4x4 and 5x4
+pub struct Connect4Index<const COLUMNS: u8 = 7, const ROWS: u8 = 6>;
+
+impl<const COLUMNS: u8, const ROWS: u8> BoardIndex<Connect4Board<COLUMNS, ROWS>>
+    for Connect4Index<COLUMNS, ROWS>
+{
+    fn size(&self) -> usize {
+        const {
+            assert!(
+                (COLUMNS as u32 * Connect4Bitboard::<COLUMNS, ROWS>::HEIGHT as u32) < usize::BITS,
+                "board too large to index by position code"
+            )
+        };
+        1 << (COLUMNS as usize * Connect4Bitboard::<COLUMNS, ROWS>::HEIGHT as usize)
+    }
+
+    fn index(&self, board: &Connect4Board<COLUMNS, ROWS>) -> Option<usize> {
+        Some(board.curr_state().position_code() as usize)
+    }
+}
@@ -1,166 +1,217 @@
 use std::ops;
 
-use crate::util::Bitboard;
+use super::{MAX_COLUMNS, square::Connect4Square};
 
-use super::square::Connect4Square;
-
-// connect 4 bitboards are in a different layout for efficiency purposes
+// connect 4 bitboards are in a different layout for efficiency purposes, each column is a group
+// of bits going up with an extra sentinel bit on top that stays empty
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Connect4Bitboard(Bitboard<7, 7>);
-
-impl Connect4Bitboard {
-    pub const NONE: Self = Self(Bitboard::NONE);
-    pub const VALID: Self = Self::row(0)
-        .bit_or(Self::row(1))
-        .bit_or(Self::row(2))
-        .bit_or(Self::row(3))
-        .bit_or(Self::row(4))
-        .bit_or(Self::row(5));
+pub struct Connect4Bitboard<const COLUMNS: u8 = 7, const ROWS: u8 = 6>(u64);
+
+impl<const COLUMNS: u8, const ROWS: u8> Connect4Bitboard<COLUMNS, ROWS> {
+    // bits per column including the sentinel
+    pub const HEIGHT: u8 = ROWS + 1;
+
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = {
+        assert!(
+            COLUMNS as u32 * Self::HEIGHT as u32 <= 64,
+            "the board and its sentinel row must fit in 64 bits"
+        );
+        assert!(COLUMNS as usize <= MAX_COLUMNS);
+        let mut result = 0u64;
+        let mut i = 0;
+        while i < COLUMNS as u32 * Self::HEIGHT as u32 {
+            result |= 1 << i;
+            i += 1;
+        }
+        Self(result)
+    };
+    pub const VALID: Self = {
+        let mut result = Self::NONE;
+        let mut row = 0;
+        while row < ROWS {
+            result = result.bit_or(Self::row(row));
+            row += 1;
+        }
+        result
+    };
 
     pub const fn from_raw(value: u64) -> Self {
-        Self(Bitboard::from_raw(value))
+        assert!((value & !Self::ALL.value()) == 0);
+        Self(value)
     }
 
-    pub const fn column(file: u8) -> Self {
-        Self(Bitboard::rank(file))
+    pub const fn column(column: u8) -> Self {
+        assert!(column < COLUMNS);
+        let mut result = 0u64;
+        let mut row = 0;
+        while row < Self::HEIGHT {
+            result |= 1 << (column * Self::HEIGHT + row);
+            row += 1;
+        }
+        Self(result)
     }
 
-    pub const fn row(rank: u8) -> Self {
-        Self(Bitboard::file(rank))
+    // rows go up to the sentinel row, ROWS
+    pub const fn row(row: u8) -> Self {
+        assert!(row < Self::HEIGHT);
+        let mut result = 0u64;
+        let mut column = 0;
+        while column < COLUMNS {
+            result |= 1 << (column * Self::HEIGHT + row);
+            column += 1;
+        }
+        Self(result)
     }
 
-    pub const fn from_square(sq: Connect4Square) -> Self {
-        Self(Bitboard::from_square(sq.to_util_sq()))
+    pub const fn from_square(sq: Connect4Square<COLUMNS, ROWS>) -> Self {
+        Self(1 << sq.value())
     }
 
     pub const fn value(self) -> u64 {
-        self.0.value()
+        self.0
     }
 
     // does not prevent bits from entering the invalid area above the board
     pub const fn north(self) -> Self {
-        Self(self.0.west())
+        Self((self.0 << 1) & Self::ALL.value())
     }
 
     // does not prevent bits from entering the invalid area above the board
     pub const fn south(self) -> Self {
-        Self(self.0.east())
+        Self(self.0 >> 1)
     }
 
     pub const fn east(self) -> Self {
-        Self(self.0.north())
+        Self((self.0 << Self::HEIGHT) & Self::ALL.value())
     }
 
     pub const fn west(self) -> Self {
         // shifting left cannot shift into invalid bits
-        Self(self.0.south())
+        Self(self.0 >> Self::HEIGHT)
+    }
+
+    // mirrors the board left to right
+    pub const fn flip_horizontal(self) -> Self {
+        let mask = (1u64 << Self::HEIGHT) - 1;
+        let mut result = 0;
+        let mut column = 0;
+        while column < COLUMNS {
+            result |= ((self.0 >> (Self::HEIGHT * column)) & mask)
+                << (Self::HEIGHT * (COLUMNS - 1 - column));
+            column += 1;
+        }
+        Self(result)
     }
 
-    pub const fn lsb(self) -> Connect4Square {
-        Connect4Square::from_util_sq(self.0.lsb())
+    pub const fn lsb(self) -> Connect4Square<COLUMNS, ROWS> {
+        Connect4Square::from_raw(self.0.trailing_zeros() as u16)
     }
 
-    pub const fn msb(self) -> Connect4Square {
-        Connect4Square::from_util_sq(self.0.msb())
+    pub const fn msb(self) -> Connect4Square<COLUMNS, ROWS> {
+        Connect4Square::from_raw((63 - self.0.leading_zeros()) as u16)
     }
 
     pub const fn popcount(self) -> u32 {
-        self.0.popcount()
+        self.0.count_ones()
     }
 
-    pub fn poplsb(&mut self) -> Connect4Square {
-        Connect4Square::from_util_sq(self.0.poplsb())
+    pub fn poplsb(&mut self) -> Connect4Square<COLUMNS, ROWS> {
+        let lsb = self.lsb();
+        self.0 &= self.0 - 1;
+        lsb
     }
 
     pub const fn any(self) -> bool {
-        self.0.any()
+        self.0 > 0
     }
 
     pub const fn empty(self) -> bool {
-        self.0.empty()
+        self.0 == 0
     }
 
     pub const fn multiple(self) -> bool {
-        self.0.multiple()
+        self.0 & (self.0.wrapping_sub(1)) > 0
     }
 
     pub const fn one(self) -> bool {
-        self.0.one()
+        self.any() && !self.multiple()
     }
 
-    pub fn set(&mut self, sq: Connect4Square) {
-        self.0.set(sq.to_util_sq());
+    pub fn set(&mut self, sq: Connect4Square<COLUMNS, ROWS>) {
+        self.0 |= 1 << sq.value();
     }
 
-    pub fn toggle(&mut self, sq: Connect4Square) {
-        self.0.toggle(sq.to_util_sq());
+    pub fn toggle(&mut self, sq: Connect4Square<COLUMNS, ROWS>) {
+        self.0 ^= 1 << sq.value();
     }
 
-    pub fn unset(&mut self, sq: Connect4Square) {
-        self.0.unset(sq.to_util_sq());
+    pub fn unset(&mut self, sq: Connect4Square<COLUMNS, ROWS>) {
+        self.0 &= !(1 << sq.value());
     }
 
-    pub const fn has(self, sq: Connect4Square) -> bool {
-        self.0.has(sq.to_util_sq())
+    pub const fn has(self, sq: Connect4Square<COLUMNS, ROWS>) -> bool {
+        (self.0 >> sq.value()) & 1 > 0
     }
 
     pub const fn bit_and(self, rhs: Self) -> Self {
-        Self(self.0.bit_and(rhs.0))
+        Self(self.0 & rhs.0)
     }
 
     pub const fn bit_or(self, rhs: Self) -> Self {
-        Self(self.0.bit_or(rhs.0))
+        Self(self.0 | rhs.0)
     }
 
     pub const fn bit_xor(self, rhs: Self) -> Self {
-        Self(self.0.bit_xor(rhs.0))
+        Self(self.0 ^ rhs.0)
     }
 
+    // keeps the sentinel bits and everything past the board clear
     pub const fn bit_not(self) -> Self {
-        Self(self.0.bit_not())
+        Self(!self.0 & Self::ALL.value())
     }
 }
 
-impl ops::BitAnd for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitAnd for Connect4Bitboard<COLUMNS, ROWS> {
     type Output = Self;
     fn bitand(self, rhs: Self) -> Self::Output {
         self.bit_and(rhs)
     }
 }
 
-impl ops::BitAndAssign for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitAndAssign for Connect4Bitboard<COLUMNS, ROWS> {
     fn bitand_assign(&mut self, rhs: Self) {
         *self = self.bit_and(rhs);
     }
 }
 
-impl ops::BitOr for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitOr for Connect4Bitboard<COLUMNS, ROWS> {
     type Output = Self;
     fn bitor(self, rhs: Self) -> Self::Output {
         self.bit_or(rhs)
     }
 }
 
-impl ops::BitOrAssign for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitOrAssign for Connect4Bitboard<COLUMNS, ROWS> {
     fn bitor_assign(&mut self, rhs: Self) {
         *self = self.bit_or(rhs);
     }
 }
 
-impl ops::BitXor for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitXor for Connect4Bitboard<COLUMNS, ROWS> {
     type Output = Self;
     fn bitxor(self, rhs: Self) -> Self::Output {
         self.bit_xor(rhs)
     }
 }
 
-impl ops::BitXorAssign for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::BitXorAssign for Connect4Bitboard<COLUMNS, ROWS> {
     fn bitxor_assign(&mut self, rhs: Self) {
         *self = self.bit_xor(rhs)
     }
 }
 
-impl ops::Not for Connect4Bitboard {
+impl<const COLUMNS: u8, const ROWS: u8> ops::Not for Connect4Bitboard<COLUMNS, ROWS> {
     type Output = Self;
     fn not(self) -> Self::Output {
         self.bit_not()
@@ -1,35 +1,34 @@
-use crate::util::Square;
-
+// squares are numbered up each column in turn, with a sentinel square above every column
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Connect4Square(Square<7, 7>);
+pub struct Connect4Square<const COLUMNS: u8 = 7, const ROWS: u8 = 6>(u16);
 
-impl Connect4Square {
-    pub const fn from_row_column(row: u8, column: u8) -> Self {
-        // swapped intentionally
-        Self(Square::from_rank_file(column, row))
-    }
+impl<const COLUMNS: u8, const ROWS: u8> Connect4Square<COLUMNS, ROWS> {
+    // bits per column including the sentinel
+    pub const HEIGHT: u8 = ROWS + 1;
 
-    pub const fn from_util_sq(sq: Square<7, 7>) -> Self {
-        Self(sq)
+    pub const fn from_row_column(row: u8, column: u8) -> Self {
+        assert!(row < Self::HEIGHT && column < COLUMNS);
+        Self(column as u16 * Self::HEIGHT as u16 + row as u16)
     }
 
     pub const fn from_raw(sq: u16) -> Self {
-        Self(Square::from_raw(sq))
-    }
-
-    pub const fn to_util_sq(self) -> Square<7, 7> {
-        self.0
+        assert!(sq < COLUMNS as u16 * Self::HEIGHT as u16);
+        Self(sq)
     }
 
     pub const fn value(self) -> u16 {
-        self.0.value()
+        self.0
     }
 
     pub const fn row(self) -> u8 {
-        self.0.file()
+        (self.0 % Self::HEIGHT as u16) as u8
     }
 
     pub const fn column(self) -> u8 {
-        self.0.rank()
+        (self.0 / Self::HEIGHT as u16) as u8
+    }
+
+    pub const fn flip_horizontal(self) -> Self {
+        Self::from_row_column(self.row(), COLUMNS - 1 - self.column())
     }
 }
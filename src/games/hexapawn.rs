@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use arrayvec::ArrayVec;
 
 use crate::{
-    games::board::{CopyMakeBoard, CopyMakeWrapper, GameResult},
+    games::board::{BoardIndex, CopyMakeBoard, CopyMakeWrapper, GameResult},
     util::{Bitboard, Square, parse_fen_pieces},
 };
 
@@ -193,3 +193,22 @@ impl fmt::Display for HexapawnState {
 }
 
 pub type HexapawnBoard = CopyMakeWrapper<HexapawnState>;
+
+// each square in base 3, then the side to move
+pub struct HexapawnIndex;
+
+impl BoardIndex<HexapawnBoard> for HexapawnIndex {
+    fn size(&self) -> usize {
+        3usize.pow(9) * 2
+    }
+
+    fn index(&self, board: &HexapawnBoard) -> Option<usize> {
+        let state = board.curr_state();
+        let mut index = 0;
+        for sq in (0..9).rev() {
+            let piece = state.piece_on(HexapawnSquare::from_raw(sq));
+            index = 3 * index + piece.map_or(0, |c| c as usize + 1);
+        }
+        Some(2 * index + state.stm as usize)
+    }
+}
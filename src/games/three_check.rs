@@ -1,19 +1,25 @@
 pub mod attacks;
+pub mod book;
 mod castling_rooks;
 mod movegen;
+pub mod pgn;
+pub mod polyglot;
+pub mod san;
 pub mod see;
+#[cfg(test)]
+mod tests;
 pub mod types;
 mod zobrist;
 
 use core::fmt;
 
 use castling_rooks::CastlingRooks;
-pub use movegen::MoveList;
+pub use movegen::{GenMode, MoveList};
 pub use see::see;
-pub use types::{Bitboard, Color, Move, MoveKind, Piece, PieceType, Square};
+pub use types::{Bitboard, Color, Move, MoveKind, Piece, PieceType, Square, Variant};
 pub use zobrist::ZobristKey;
 
-use super::board::{Board, CopyMakeBoard, CopyMakeWrapper, GameResult};
+use super::board::{Board, CopyMakeBoard, CopyMakeWrapper, GameResult, HashedBoard};
 
 #[derive(Debug, Clone)]
 pub struct ThreeCheckState {
@@ -23,25 +29,89 @@ pub struct ThreeCheckState {
     checkers: Bitboard,
     diag_pinned: Bitboard,
     hv_pinned: Bitboard,
+    // squares a pawn, knight, bishop or rook of the side to move would give check from
+    check_squares: [Bitboard; 4],
+    // pieces of the side to move that would give a discovered check by moving off the line
+    discoverers: Bitboard,
     castling_rooks: CastlingRooks,
     stm: Color,
     ep_square: Option<Square>,
     half_move_clock: u8,
     check_count: [u8; 2],
+    check_limit: Option<u8>,
+    variant: Variant,
     zkey: ZobristKey,
+    pawn_key: ZobristKey,
+}
+
+// everything make_move destroys, so the move can be taken back without copying the state
+#[derive(Debug, Clone)]
+pub struct Undo {
+    mv: Move,
+    moved: Piece,
+    captured: Option<Piece>,
+    // pieces next to an atomic capture that went up along with it, four bits each
+    // in the order they pop off the blast bitboard
+    blast: Bitboard,
+    exploded: u32,
+    castling_rooks: CastlingRooks,
+    ep_square: Option<Square>,
+    half_move_clock: u8,
+    check_count: [u8; 2],
+    checkers: Bitboard,
+    diag_pinned: Bitboard,
+    hv_pinned: Bitboard,
+    check_squares: [Bitboard; 4],
+    discoverers: Bitboard,
+    zkey: ZobristKey,
+    pawn_key: ZobristKey,
 }
 
 impl ThreeCheckState {
-    const STARTPOS_FEN: &'static str =
-        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1";
+    const STARTPOS_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
+    pub const MAX_CHECK_LIMIT: u8 = 15;
+
+    // d4, e4, d5 and e5
+    pub const HILL: Bitboard = Bitboard::from_raw(0x0000001818000000);
+
+    // fens with a check count field are assumed to be three check, since the field only holds the
+    // checks remaining, and standard 6 field fens are regular chess. other limits need
+    // from_fen_with_limit
     pub fn from_fen(fen: &str) -> Option<Self> {
+        let parts: Vec<&str> = fen.split_whitespace().collect();
+        let check_limit = if parts.len() == 7 { Some(3) } else { None };
+        Self::from_fen_with_limit(fen, check_limit)
+    }
+
+    // check_limit of None disables checks entirely, in which case the check count field is optional and ignored
+    pub fn from_fen_with_limit(fen: &str, check_limit: Option<u8>) -> Option<Self> {
+        Self::from_fen_with_options(fen, check_limit, Variant::Standard)
+    }
+
+    // antichess positions need the variant while parsing, since they can have any number of kings
+    // and never have castling rights or checks
+    pub fn from_fen_with_options(
+        fen: &str,
+        mut check_limit: Option<u8>,
+        variant: Variant,
+    ) -> Option<Self> {
         let mut board = Self::empty();
+        board.variant = variant;
+        let antichess = variant == Variant::Antichess;
+        if antichess {
+            check_limit = None;
+        }
 
         let parts: Vec<&str> = fen.split_whitespace().collect();
-        if parts.len() != 7 {
+        if parts.len() != 6 && parts.len() != 7 {
             return None;
         }
+        let (check_field, clock_field) = if parts.len() == 7 {
+            (Some(parts[4]), parts[5])
+        } else {
+            (None, parts[4])
+        };
 
         let mut curr = Square::from_rank_file(7, 0).value() as i32;
         let mut rows = 0;
@@ -131,38 +201,63 @@ impl ThreeCheckState {
             return None;
         };
 
+        for color in [Color::White, Color::Black] {
+            if !antichess
+                && !board
+                    .colored_pieces(Piece::new(color, PieceType::King))
+                    .one()
+            {
+                return None;
+            }
+        }
+
         if parts[2].len() == 0 || parts[2].len() > 4 {
             return None;
         }
 
-        for c in parts[2].chars() {
-            match c {
-                'K' => {
-                    board.castling_rooks.color_mut(Color::White).king_side =
-                        Some(Square::from_rank_file(0, 7));
-                }
-                'Q' => {
-                    board.castling_rooks.color_mut(Color::White).queen_side =
-                        Some(Square::from_rank_file(0, 0));
-                }
-                'k' => {
-                    board.castling_rooks.color_mut(Color::Black).king_side =
-                        Some(Square::from_rank_file(7, 7));
-                }
-                'q' => {
-                    board.castling_rooks.color_mut(Color::Black).queen_side =
-                        Some(Square::from_rank_file(7, 0));
-                }
-                '-' => {
-                    if parts[2].len() != 1 {
-                        return None;
-                    }
+        // KQkq pick the outermost rook, file letters (shredder and x-fen) name the rook directly
+        if parts[2] != "-" && !antichess {
+            for c in parts[2].chars() {
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let back_rank = if color == Color::White { 0 } else { 7 };
+                let king_sq = board.king_sq(color);
+                if king_sq.rank() != back_rank {
+                    return None;
                 }
-                _ => {
+                let rooks = board.colored_pieces(Piece::new(color, PieceType::Rook))
+                    & Bitboard::rank(back_rank);
+
+                let files: Vec<u8> = match c.to_ascii_lowercase() {
+                    'k' => (king_sq.file() + 1..8).rev().collect(),
+                    'q' => (0..king_sq.file()).collect(),
+                    'a'..='h' => vec![c.to_ascii_lowercase() as u8 - b'a'],
+                    _ => return None,
+                };
+                let Some(rook_sq) = files
+                    .into_iter()
+                    .map(|file| Square::from_rank_file(back_rank, file))
+                    .find(|sq| rooks.has(*sq))
+                else {
                     return None;
+                };
+
+                if rook_sq.file() > king_sq.file() {
+                    board.castling_rooks.color_mut(color).king_side = Some(rook_sq);
+                } else {
+                    board.castling_rooks.color_mut(color).queen_side = Some(rook_sq);
                 }
             }
         }
+        if !antichess {
+            board.castling_rooks.frc = board.castling_rooks.requires_frc(
+                board.king_sq(Color::White),
+                board.king_sq(Color::Black),
+            );
+        }
 
         if parts[3].len() == 0 || parts[3].len() > 2 {
             return None;
@@ -183,28 +278,30 @@ impl ThreeCheckState {
             ));
         }
 
-        let checks: Vec<&str> = parts[4].split('+').collect();
-        if checks.len() != 2 {
-            return None;
-        }
-        match checks[0].parse::<u8>() {
-            Ok(n) => {
-                board.check_count[0] = 3 - n;
-            }
-            Err(_) => {
+        board.check_limit = check_limit;
+        if let Some(limit) = check_limit {
+            if limit == 0 || limit > Self::MAX_CHECK_LIMIT {
                 return None;
             }
-        }
-        match checks[1].parse::<u8>() {
-            Ok(n) => {
-                board.check_count[1] = 3 - n;
-            }
-            Err(_) => {
-                return None;
+            if let Some(field) = check_field {
+                let checks: Vec<&str> = field.split('+').collect();
+                if checks.len() != 2 {
+                    return None;
+                }
+                for (i, remaining) in checks.iter().enumerate() {
+                    match remaining.parse::<u8>() {
+                        Ok(n) if n <= limit => {
+                            board.check_count[i] = limit - n;
+                        }
+                        _ => {
+                            return None;
+                        }
+                    }
+                }
             }
         }
 
-        match parts[5].parse::<u8>() {
+        match clock_field.parse::<u8>() {
             Ok(n) => {
                 board.half_move_clock = n;
             }
@@ -216,28 +313,68 @@ impl ThreeCheckState {
             return None;
         }
 
-        board.update_check_info();
-        board.zkey.toggle_castle_rights(board.castling_rooks());
-        if let Some(ep_square) = board.ep_square() {
-            board.zkey.toggle_ep_square(ep_square);
+        board.finish_setup();
+        Some(board)
+    }
+
+    // a standard variant position with no castling rights or en passant square, like the
+    // ones endgame tables cover. None if two pieces share a square or a king is missing
+    pub fn from_placement(
+        pieces: &[(Square, Piece)],
+        stm: Color,
+        check_count: [u8; 2],
+        check_limit: Option<u8>,
+    ) -> Option<Self> {
+        let mut board = Self::empty();
+        for &(sq, piece) in pieces {
+            if board.piece_at(sq).is_some() {
+                return None;
+            }
+            board.add_piece(sq, piece);
         }
+        for color in [Color::White, Color::Black] {
+            if !board
+                .colored_pieces(Piece::new(color, PieceType::King))
+                .one()
+            {
+                return None;
+            }
+        }
+        board.stm = stm;
+        board.check_count = check_count;
+        board.check_limit = check_limit;
+        board.finish_setup();
+        Some(board)
+    }
 
-        if board.stm() == Color::Black {
-            board.zkey.toggle_stm();
+    // check info and the parts of the key that are not pieces, once everything is placed
+    fn finish_setup(&mut self) {
+        self.update_check_info();
+        self.zkey.toggle_castle_rights(self.castling_rooks());
+        if let Some(ep_square) = self.ep_square() {
+            self.zkey.toggle_ep_square(ep_square);
         }
 
-        board
-            .zkey
-            .toggle_check(Color::White, board.check_count(Color::White));
-        board
-            .zkey
-            .toggle_check(Color::Black, board.check_count(Color::Black));
+        if self.stm() == Color::Black {
+            self.zkey.toggle_stm();
+        }
 
-        Some(board)
+        self.zkey
+            .toggle_check(Color::White, self.check_count(Color::White));
+        self.zkey
+            .toggle_check(Color::Black, self.check_count(Color::Black));
     }
 
     pub fn startpos() -> Self {
-        Self::from_fen(Self::STARTPOS_FEN).unwrap()
+        Self::startpos_with_limit(Some(3))
+    }
+
+    pub fn startpos_with_limit(check_limit: Option<u8>) -> Self {
+        Self::from_fen_with_limit(Self::STARTPOS_FEN, check_limit).unwrap()
+    }
+
+    pub fn startpos_with_options(check_limit: Option<u8>, variant: Variant) -> Self {
+        Self::from_fen_with_options(Self::STARTPOS_FEN, check_limit, variant).unwrap()
     }
 
     pub fn to_fen(&self) -> String {
@@ -282,19 +419,44 @@ impl ThreeCheckState {
             }
         }
 
-        fen += format!(
-            "{}+{} ",
-            3 - self.check_count(Color::White),
-            3 - self.check_count(Color::Black)
-        )
-        .as_str();
+        if let Some(limit) = self.check_limit {
+            fen += format!(
+                "{}+{} ",
+                limit - self.check_count(Color::White),
+                limit - self.check_count(Color::Black)
+            )
+            .as_str();
+        }
 
         fen += format!("{} 1 ", self.half_move_clock).as_str();
 
         fen
     }
 
-    pub fn make_move(&mut self, mv: Move) {
+    fn undo(&self, mv: Move, moved: Piece) -> Undo {
+        Undo {
+            mv,
+            moved,
+            captured: None,
+            blast: Bitboard::NONE,
+            exploded: 0,
+            castling_rooks: self.castling_rooks,
+            ep_square: self.ep_square,
+            half_move_clock: self.half_move_clock,
+            check_count: self.check_count,
+            checkers: self.checkers,
+            diag_pinned: self.diag_pinned,
+            hv_pinned: self.hv_pinned,
+            check_squares: self.check_squares,
+            discoverers: self.discoverers,
+            zkey: self.zkey,
+            pawn_key: self.pawn_key,
+        }
+    }
+
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        debug_assert!(self.is_pseudolegal(mv), "{} is not pseudo legal in {}", mv, self.to_fen());
+        let mut undo = self.undo(mv, self.piece_at(mv.from_sq()).unwrap());
         if let Some(ep_square) = self.ep_square() {
             self.zkey.toggle_ep_square(ep_square);
         }
@@ -336,9 +498,14 @@ impl ThreeCheckState {
                 self.remove_piece(cap_sq);
             }
             MoveKind::Castle => {
+                // in frc the king or rook can land on the other's square, so lift both first
                 let king_side = to > from;
-                self.move_piece(from, CastlingRooks::king_to(king_side, self.stm()));
-                self.move_piece(to, CastlingRooks::rook_to(king_side, self.stm()));
+                let king = self.piece_at(from).unwrap();
+                let rook = self.piece_at(to).unwrap();
+                self.remove_piece(from);
+                self.remove_piece(to);
+                self.add_piece(CastlingRooks::king_to(king_side, self.stm()), king);
+                self.add_piece(CastlingRooks::rook_to(king_side, self.stm()), rook);
             }
         }
 
@@ -360,7 +527,11 @@ impl ThreeCheckState {
             if cap.piece_type() == PieceType::Rook {
                 self.castling_rooks.color_mut(self.stm().flip()).remove(to);
             }
+            if self.variant == Variant::Atomic {
+                (undo.blast, undo.exploded) = self.explode(to);
+            }
         }
+        undo.captured = captured;
 
         self.stm = self.stm.flip();
 
@@ -372,14 +543,16 @@ impl ThreeCheckState {
 
         self.update_check_info();
 
-        if self.checkers().any() {
+        if self.check_limit.is_some() && self.checkers().any() {
             self.zkey.toggle_check(self.stm, self.check_count(self.stm));
             self.check_count[self.stm as usize] += 1;
             self.zkey.toggle_check(self.stm, self.check_count(self.stm));
         }
+        undo
     }
 
-    pub fn make_null_move(&mut self) {
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = self.undo(Move::NULL, Piece::new(self.stm, PieceType::King));
         self.half_move_clock += 1;
         if let Some(ep) = self.ep_square {
             self.zkey.toggle_ep_square(ep);
@@ -390,6 +563,62 @@ impl ThreeCheckState {
         self.stm = self.stm.flip();
 
         self.update_check_info();
+        undo
+    }
+
+    // takes back the move that produced undo, which has to be the last one made
+    pub fn unmake_move(&mut self, undo: &Undo) {
+        self.stm = self.stm.flip();
+        let mv = undo.mv;
+        if mv != Move::NULL {
+            let from = mv.from_sq();
+            let to = mv.to_sq();
+            match mv.kind() {
+                MoveKind::Castle => {
+                    let king_side = to > from;
+                    let rook = self.piece_at(CastlingRooks::rook_to(king_side, self.stm)).unwrap();
+                    self.lift_piece(CastlingRooks::king_to(king_side, self.stm));
+                    self.lift_piece(CastlingRooks::rook_to(king_side, self.stm));
+                    self.put_piece(from, undo.moved);
+                    self.put_piece(to, rook);
+                }
+                _ => {
+                    // an atomic capture leaves nothing on the target square
+                    if self.variant == Variant::Atomic && undo.captured.is_some() {
+                        let mut blast = undo.blast;
+                        let mut exploded = undo.exploded;
+                        while blast.any() {
+                            self.put_piece(blast.poplsb(), Piece::from_raw((exploded & 15) as u8));
+                            exploded >>= 4;
+                        }
+                    } else {
+                        self.lift_piece(to);
+                    }
+                    self.put_piece(from, undo.moved);
+
+                    if let Some(captured) = undo.captured {
+                        let cap_sq = match mv.kind() {
+                            MoveKind::Enpassant if self.stm == Color::White => to - 8,
+                            MoveKind::Enpassant => to + 8,
+                            _ => to,
+                        };
+                        self.put_piece(cap_sq, captured);
+                    }
+                }
+            }
+        }
+
+        self.castling_rooks = undo.castling_rooks;
+        self.ep_square = undo.ep_square;
+        self.half_move_clock = undo.half_move_clock;
+        self.check_count = undo.check_count;
+        self.checkers = undo.checkers;
+        self.diag_pinned = undo.diag_pinned;
+        self.hv_pinned = undo.hv_pinned;
+        self.check_squares = undo.check_squares;
+        self.discoverers = undo.discoverers;
+        self.zkey = undo.zkey;
+        self.pawn_key = undo.pawn_key;
     }
 
     pub fn stm(&self) -> Color {
@@ -425,6 +654,15 @@ impl ThreeCheckState {
         self.castling_rooks
     }
 
+    pub fn is_frc(&self) -> bool {
+        self.castling_rooks.frc
+    }
+
+    // switches castling to frc notation, standard positions can always be written either way
+    pub fn enable_frc(&mut self) {
+        self.castling_rooks.frc = true;
+    }
+
     pub fn piece_at(&self, sq: Square) -> Option<Piece> {
         self.squares[sq.value() as usize]
     }
@@ -433,14 +671,367 @@ impl ThreeCheckState {
         self.check_count[c as usize]
     }
 
+    pub fn check_limit(&self) -> Option<u8> {
+        self.check_limit
+    }
+
+    // checks c can still receive before losing, None if checks do not matter
+    pub fn checks_remaining(&self, c: Color) -> Option<u8> {
+        self.check_limit.map(|limit| limit - self.check_count(c))
+    }
+
+    pub fn check_limit_reached(&self) -> bool {
+        self.checks_remaining(self.stm) == Some(0)
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    // only the side to move can be missing its king, the mover may never blow up its own
+    pub fn king_exploded(&self) -> bool {
+        self.variant == Variant::Atomic
+            && self
+                .colored_pieces(Piece::new(self.stm, PieceType::King))
+                .empty()
+    }
+
+    // the opponent's king made it to the centre
+    pub fn hill_reached(&self) -> bool {
+        self.variant == Variant::KingOfTheHill && Self::HILL.has(self.king_sq(self.stm.flip()))
+    }
+
+    // side to move has lost to a variant rule rather than by mate
+    pub fn variant_loss(&self) -> bool {
+        self.check_limit_reached() || self.hill_reached() || self.king_exploded()
+    }
+
+    // legality of a pseudo legal atomic move, castles are checked during generation
+    pub fn is_atomic_legal(&self, mv: Move) -> bool {
+        if mv.kind() == MoveKind::Castle {
+            return true;
+        }
+        let us = self.stm;
+        let them = us.flip();
+        let from = mv.from_sq();
+        let to = mv.to_sq();
+        let from_bb = Bitboard::from_square(from);
+        let to_bb = Bitboard::from_square(to);
+        let our_king = self.colored_pieces(Piece::new(us, PieceType::King));
+        let their_king = self.colored_pieces(Piece::new(them, PieceType::King));
+
+        let cap_sq = match mv.kind() {
+            MoveKind::Enpassant if us == Color::White => Some(to - 8),
+            MoveKind::Enpassant => Some(to + 8),
+            _ if self.piece_at(to).is_some() => Some(to),
+            _ => None,
+        };
+
+        let mut occ = self.occ() ^ from_bb;
+        let king_sq = if our_king.has(from) { to } else { our_king.lsb() };
+        let mut enemies = self.colors(them);
+        match cap_sq {
+            Some(cap_sq) => {
+                let blast = (attacks::king_attacks(to) & self.occ() & !self.pieces(PieceType::Pawn))
+                    | from_bb
+                    | to_bb
+                    | Bitboard::from_square(cap_sq);
+                if (blast & our_king).any() {
+                    return false;
+                }
+                if (blast & their_king).any() {
+                    return true;
+                }
+                occ &= !blast;
+                enemies &= !blast;
+            }
+            None => {
+                occ |= to_bb;
+            }
+        }
+
+        // connected kings can never be in check
+        if (attacks::king_attacks(king_sq) & their_king).any() {
+            return true;
+        }
+        (self.all_attackers_to(king_sq, occ) & enemies & occ).empty()
+    }
+
+    // whether movegen could have produced mv, ignoring king safety and forced captures.
+    // cheap enough to vet tt moves and moves from outside before trusting make_move with them
+    pub fn is_pseudolegal(&self, mv: Move) -> bool {
+        if mv == Move::NULL {
+            return false;
+        }
+        let us = self.stm;
+        let them = us.flip();
+        let from = mv.from_sq();
+        let to = mv.to_sq();
+        let Some(piece) = self.piece_at(from) else {
+            return false;
+        };
+        if piece.color() != us {
+            return false;
+        }
+        let pt = piece.piece_type();
+
+        if mv.kind() == MoveKind::Castle {
+            return pt == PieceType::King
+                && self.checkers.empty()
+                && movegen::castle_move(self, to > from) == Some(mv);
+        }
+        if self.colors(us).has(to) {
+            return false;
+        }
+
+        if pt != PieceType::Pawn {
+            if mv.kind() != MoveKind::None {
+                return false;
+            }
+            let occ = self.occ();
+            let targets = match pt {
+                PieceType::Knight => attacks::knight_attacks(from),
+                PieceType::Bishop => attacks::bishop_attacks(from, occ),
+                PieceType::Rook => attacks::rook_attacks(from, occ),
+                PieceType::Queen => attacks::queen_attacks(from, occ),
+                // atomic kings cannot capture
+                _ if self.variant == Variant::Atomic => attacks::king_attacks(from) & !occ,
+                _ => attacks::king_attacks(from),
+            };
+            return targets.has(to);
+        }
+
+        if mv.kind() == MoveKind::Enpassant {
+            return self.ep_square == Some(to) && attacks::pawn_attacks(us, from).has(to);
+        }
+
+        let from_bb = Bitboard::from_square(from);
+        let third_rank = if us == Color::White {
+            Bitboard::RANK_0.north().north()
+        } else {
+            Bitboard::LAST_RANK.south().south()
+        };
+        let pushes = attacks::pawn_pushes_bb(us, from_bb) & !self.occ();
+        let double_pushes = attacks::pawn_pushes_bb(us, pushes & third_rank) & !self.occ();
+        let captures = attacks::pawn_attacks(us, from) & self.colors(them);
+        if !(pushes | double_pushes | captures).has(to) {
+            return false;
+        }
+
+        let last_rank = if us == Color::White {
+            Bitboard::LAST_RANK
+        } else {
+            Bitboard::RANK_0
+        };
+        match mv.kind() {
+            MoveKind::Promotion => {
+                last_rank.has(to)
+                    && match mv.promo_piece() {
+                        PieceType::King => self.variant == Variant::Antichess,
+                        _ => true,
+                    }
+            }
+            _ => !last_rank.has(to),
+        }
+    }
+
+    // agrees with gen_moves, without generating anything in the common case
+    pub fn is_legal(&self, mv: Move) -> bool {
+        if !self.is_pseudolegal(mv) {
+            return false;
+        }
+        match self.variant {
+            Variant::Atomic => return !self.king_exploded() && self.is_atomic_legal(mv),
+            Variant::Antichess => return self.is_capture(mv) || !self.has_capture(),
+            _ => {}
+        }
+        // castles are fully checked as pseudo legal moves
+        if mv.kind() == MoveKind::Castle {
+            return true;
+        }
+
+        let us = self.stm;
+        let from = mv.from_sq();
+        let to = mv.to_sq();
+        let cap_bb = match mv.kind() {
+            MoveKind::Enpassant if us == Color::White => Bitboard::from_square(to - 8),
+            MoveKind::Enpassant => Bitboard::from_square(to + 8),
+            _ => Bitboard::from_square(to),
+        };
+        let occ = (self.occ() ^ Bitboard::from_square(from) ^ (cap_bb & self.occ()))
+            | Bitboard::from_square(to);
+        let king_sq = if from == self.king_sq(us) {
+            to
+        } else {
+            self.king_sq(us)
+        };
+        (self.all_attackers_to(king_sq, occ) & self.colors(us.flip()) & !cap_bb).empty()
+    }
+
+    pub fn is_capture(&self, mv: Move) -> bool {
+        match mv.kind() {
+            MoveKind::Enpassant => true,
+            MoveKind::Castle => false,
+            _ => self.piece_at(mv.to_sq()).is_some(),
+        }
+    }
+
+    // what noisy movegen produces
+    pub fn is_noisy(&self, mv: Move) -> bool {
+        self.is_capture(mv)
+            || mv.kind() == MoveKind::Promotion && mv.promo_piece() == PieceType::Queen
+    }
+
+    pub fn check_squares(&self, pt: PieceType) -> Bitboard {
+        match pt {
+            PieceType::Queen => {
+                self.check_squares[PieceType::Bishop as usize]
+                    | self.check_squares[PieceType::Rook as usize]
+            }
+            PieceType::King => Bitboard::NONE,
+            _ => self.check_squares[pt as usize],
+        }
+    }
+
+    pub fn discoverers(&self) -> Bitboard {
+        self.discoverers
+    }
+
+    // whether a legal mv checks the opponent, without making it. atomic explosions
+    // change too much to predict, so those moves are still made on a copy
+    pub fn gives_check(&self, mv: Move) -> bool {
+        match self.variant {
+            Variant::Antichess => return false,
+            Variant::Atomic => {
+                let mut next = self.clone();
+                next.make_move(mv);
+                return next.checkers().any();
+            }
+            _ => {}
+        }
+
+        let us = self.stm;
+        let their_king = self.king_sq(us.flip());
+        let from = mv.from_sq();
+        let to = mv.to_sq();
+        let discovered =
+            self.discoverers.has(from) && !attacks::line_through(their_king, from).has(to);
+        match mv.kind() {
+            MoveKind::None => {
+                let pt = self.piece_at(from).unwrap().piece_type();
+                discovered || self.check_squares(pt).has(to)
+            }
+            // the pawn may have been the only thing between the new piece and the king
+            MoveKind::Promotion => {
+                let occ = (self.occ() ^ Bitboard::from_square(from)) | Bitboard::from_square(to);
+                discovered
+                    || attacks::piece_attacks(mv.promo_piece(), us, to, occ).has(their_king)
+            }
+            // both lift two pieces, so look for any slider that can see the king afterwards
+            MoveKind::Enpassant | MoveKind::Castle => {
+                let mut occ = self.occ() ^ Bitboard::from_square(from);
+                let diags = self.colored_pieces(Piece::new(us, PieceType::Bishop))
+                    | self.colored_pieces(Piece::new(us, PieceType::Queen));
+                let mut hvs = self.colored_pieces(Piece::new(us, PieceType::Rook))
+                    | self.colored_pieces(Piece::new(us, PieceType::Queen));
+                if mv.kind() == MoveKind::Enpassant {
+                    let cap_sq = if us == Color::White { to - 8 } else { to + 8 };
+                    occ ^= Bitboard::from_square(cap_sq);
+                    occ |= Bitboard::from_square(to);
+                    if self.check_squares(PieceType::Pawn).has(to) {
+                        return true;
+                    }
+                } else {
+                    let king_side = to > from;
+                    let rook_dst = Bitboard::from_square(CastlingRooks::rook_to(king_side, us));
+                    occ ^= Bitboard::from_square(to);
+                    occ |= Bitboard::from_square(CastlingRooks::king_to(king_side, us)) | rook_dst;
+                    hvs = (hvs ^ Bitboard::from_square(to)) | rook_dst;
+                }
+                (attacks::bishop_attacks(their_king, occ) & diags).any()
+                    || (attacks::rook_attacks(their_king, occ) & hvs).any()
+            }
+        }
+    }
+
+    // whether the side to move can capture anything, which is what forces antichess moves
+    fn has_capture(&self) -> bool {
+        let us = self.stm;
+        let them = us.flip();
+        let occ = self.occ();
+        let pawns = self.colored_pieces(Piece::new(us, PieceType::Pawn));
+        if (attacks::pawn_attacks_bb(us, pawns) & self.colors(them)).any() {
+            return true;
+        }
+        if let Some(ep_square) = self.ep_square
+            && (attacks::pawn_attacks(them, ep_square) & pawns).any()
+        {
+            return true;
+        }
+
+        let mut pieces = self.colors(us) & !pawns;
+        while pieces.any() {
+            let sq = pieces.poplsb();
+            let targets = match self.piece_at(sq).unwrap().piece_type() {
+                PieceType::Knight => attacks::knight_attacks(sq),
+                PieceType::Bishop => attacks::bishop_attacks(sq, occ),
+                PieceType::Rook => attacks::rook_attacks(sq, occ),
+                PieceType::Queen => attacks::queen_attacks(sq, occ),
+                _ => attacks::king_attacks(sq),
+            };
+            if (targets & self.colors(them)).any() {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn gen_moves(&self) -> MoveList {
+        self.gen_moves_mode(GenMode::All)
+    }
+
+    pub fn gen_moves_mode(&self, mode: GenMode) -> MoveList {
+        let mut moves = MoveList::new();
+        movegen::movegen(self, mode, &mut moves);
+        moves
+    }
+
+    pub fn game_result(&self) -> GameResult {
+        if self.variant_loss() {
+            return GameResult::LOSS;
+        }
+
+        // losing every piece means having no moves, and being stuck wins antichess
+        if self.variant() == Variant::Antichess {
+            if self.gen_moves().len() == 0 {
+                return GameResult::WIN;
+            }
+            return GameResult::NONE;
+        }
+
+        if self.gen_moves().len() == 0 {
+            if self.checkers().any() {
+                return GameResult::LOSS;
+            } else {
+                return GameResult::DRAW;
+            }
+        }
+
+        return GameResult::NONE;
+    }
+
     pub fn attacked_by(&self, sq: Square, c: Color) -> bool {
+        let occ = self.occ() ^ self.colored_pieces(Piece::new(c.flip(), PieceType::King));
+        self.attacked_by_occ(sq, c, occ)
+    }
+
+    pub fn attacked_by_occ(&self, sq: Square, c: Color, occ: Bitboard) -> bool {
         let diags =
             self.colors(c) & (self.pieces(PieceType::Bishop) | self.pieces(PieceType::Queen));
         let hvs = self.colors(c) & (self.pieces(PieceType::Rook) | self.pieces(PieceType::Queen));
         let pawns = self.colored_pieces(Piece::new(c, PieceType::Pawn));
         let knights = self.colored_pieces(Piece::new(c, PieceType::Knight));
         let king = self.colored_pieces(Piece::new(c, PieceType::King));
-        let occ = self.occ() ^ self.colored_pieces(Piece::new(c.flip(), PieceType::King));
 
         (attacks::pawn_attacks(c.flip(), sq) & pawns).any()
             || (attacks::knight_attacks(sq) & knights).any()
@@ -514,6 +1105,10 @@ impl ThreeCheckState {
         self.zkey
     }
 
+    pub fn pawn_key(&self) -> ZobristKey {
+        self.pawn_key
+    }
+
     pub fn recompute_zkey(&self) -> ZobristKey {
         let mut key = ZobristKey::new();
         for i in 0..64 {
@@ -542,12 +1137,17 @@ impl ThreeCheckState {
             checkers: Bitboard::NONE,
             diag_pinned: Bitboard::NONE,
             hv_pinned: Bitboard::NONE,
+            check_squares: [Bitboard::NONE; 4],
+            discoverers: Bitboard::NONE,
             castling_rooks: CastlingRooks::DEFAULT,
             stm: Color::White,
             ep_square: None,
             half_move_clock: 0,
             check_count: [0; 2],
+            check_limit: None,
+            variant: Variant::Standard,
             zkey: ZobristKey::new(),
+            pawn_key: ZobristKey::new(),
         }
     }
 
@@ -558,6 +1158,9 @@ impl ThreeCheckState {
         self.squares[sq.value() as usize] = Some(piece);
 
         self.zkey.toggle_piece(piece, sq);
+        if piece.piece_type() == PieceType::Pawn {
+            self.pawn_key.toggle_piece(piece, sq);
+        }
     }
 
     fn remove_piece(&mut self, sq: Square) {
@@ -568,6 +1171,9 @@ impl ThreeCheckState {
         self.squares[sq.value() as usize] = None;
 
         self.zkey.toggle_piece(piece, sq);
+        if piece.piece_type() == PieceType::Pawn {
+            self.pawn_key.toggle_piece(piece, sq);
+        }
     }
 
     fn move_piece(&mut self, from: Square, to: Square) {
@@ -580,9 +1186,84 @@ impl ThreeCheckState {
 
         self.zkey.toggle_piece(piece, from);
         self.zkey.toggle_piece(piece, to);
+        if piece.piece_type() == PieceType::Pawn {
+            self.pawn_key.toggle_piece(piece, from);
+            self.pawn_key.toggle_piece(piece, to);
+        }
+    }
+
+    // unmake restores both keys wholesale, so it skips the hashing
+    fn put_piece(&mut self, sq: Square, piece: Piece) {
+        let sq_bb = Bitboard::from_square(sq);
+        self.pieces[piece.piece_type() as usize] |= sq_bb;
+        self.colors[piece.color() as usize] |= sq_bb;
+        self.squares[sq.value() as usize] = Some(piece);
+    }
+
+    fn lift_piece(&mut self, sq: Square) {
+        let piece = self.piece_at(sq).unwrap();
+        let sq_bb = Bitboard::from_square(sq);
+        self.pieces[piece.piece_type() as usize] ^= sq_bb;
+        self.colors[piece.color() as usize] ^= sq_bb;
+        self.squares[sq.value() as usize] = None;
+    }
+
+    // removes the capturing piece and every non pawn around it, returning the latter
+    fn explode(&mut self, sq: Square) -> (Bitboard, u32) {
+        self.remove_piece(sq);
+        let blast = attacks::king_attacks(sq) & self.occ() & !self.pieces(PieceType::Pawn);
+        let mut exploded = 0;
+        let mut remaining = blast;
+        let mut shift = 0;
+        while remaining.any() {
+            let sq = remaining.poplsb();
+            let piece = self.piece_at(sq).unwrap();
+            match piece.piece_type() {
+                PieceType::Rook => self.castling_rooks.color_mut(piece.color()).remove(sq),
+                PieceType::King => self.castling_rooks.color_mut(piece.color()).remove_both(),
+                _ => {}
+            }
+            self.remove_piece(sq);
+            exploded |= (piece as u32) << shift;
+            shift += 4;
+        }
+        (blast, exploded)
+    }
+
+    // no pins in atomic since moves are checked for legality one by one, and connected kings
+    // cannot give check because capturing a king would blow up your own
+    fn update_atomic_check_info(&mut self) {
+        self.diag_pinned = Bitboard::NONE;
+        self.hv_pinned = Bitboard::NONE;
+        self.checkers = Bitboard::NONE;
+
+        let our_king = self.colored_pieces(Piece::new(self.stm(), PieceType::King));
+        let their_king = self.colored_pieces(Piece::new(self.stm().flip(), PieceType::King));
+        if our_king.empty()
+            || their_king.empty()
+            || (attacks::king_attacks(our_king.lsb()) & their_king).any()
+        {
+            return;
+        }
+        self.checkers = self.attackers_to(our_king.lsb(), self.stm().flip()) & !their_king;
     }
 
     fn update_check_info(&mut self) {
+        match self.variant {
+            Variant::Atomic => {
+                self.update_atomic_check_info();
+                return;
+            }
+            // there is no check in antichess
+            Variant::Antichess => {
+                self.checkers = Bitboard::NONE;
+                self.diag_pinned = Bitboard::NONE;
+                self.hv_pinned = Bitboard::NONE;
+                return;
+            }
+            _ => {}
+        }
+
         let king_sq = self.king_sq(self.stm());
         self.checkers = self.attackers_to(king_sq, self.stm().flip());
 
@@ -620,22 +1301,57 @@ impl ThreeCheckState {
                 self.hv_pinned |= between;
             }
         }
+
+        self.update_check_squares();
+    }
+
+    fn update_check_squares(&mut self) {
+        let us = self.stm();
+        let their_king = self.king_sq(us.flip());
+        let occ = self.occ();
+        self.check_squares = [
+            attacks::pawn_attacks(us.flip(), their_king),
+            attacks::knight_attacks(their_king),
+            attacks::bishop_attacks(their_king, occ),
+            attacks::rook_attacks(their_king, occ),
+        ];
+
+        self.discoverers = Bitboard::NONE;
+        let queens = self.colored_pieces(Piece::new(us, PieceType::Queen));
+        let rooks = self.colored_pieces(Piece::new(us, PieceType::Rook));
+        let bishops = self.colored_pieces(Piece::new(us, PieceType::Bishop));
+        let mut sliders = (attacks::bishop_attacks(their_king, Bitboard::NONE) & (bishops | queens))
+            | (attacks::rook_attacks(their_king, Bitboard::NONE) & (rooks | queens));
+        let block_mask = occ ^ sliders;
+        while sliders.any() {
+            let between = attacks::line_between(their_king, sliders.poplsb()) & block_mask;
+            if between.one() {
+                self.discoverers |= between & self.colors(us);
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ThreeCheckBoard {
-    states: Vec<ThreeCheckState>,
+    state: ThreeCheckState,
+    undos: Vec<Undo>,
     pub keys: Vec<ZobristKey>,
 }
 
 impl ThreeCheckBoard {
-    pub fn curr_state(&self) -> &ThreeCheckState {
-        self.states.last().unwrap()
+    pub fn from_state(state: ThreeCheckState) -> Self {
+        let mut result = Self {
+            state,
+            undos: Vec::new(),
+            keys: Vec::new(),
+        };
+        result.keys.push(result.state.zkey());
+        result
     }
 
-    fn curr_state_mut(&mut self) -> &mut ThreeCheckState {
-        self.states.last_mut().unwrap()
+    pub fn curr_state(&self) -> &ThreeCheckState {
+        &self.state
     }
 
     pub fn is_drawn(&self, two_fold: bool) -> bool {
@@ -669,70 +1385,87 @@ impl Board for ThreeCheckBoard {
     type MoveList = MoveList;
 
     fn startpos() -> Self {
-        let mut result = Self {
-            states: vec![ThreeCheckState::startpos()],
-            keys: Vec::new()
-        };
-        result.keys.push(result.states[0].zkey());
-        result
+        Self::from_state(ThreeCheckState::startpos())
     }
 
     fn from_fen(fen: &str) -> Option<Self> {
-        let state = ThreeCheckState::from_fen(fen);
-        if let Some(s) = state {
-            let mut result = Self {
-                states: vec![s],
-                keys: Vec::new()
-            };
-            result.keys.push(result.states[0].zkey());
-            Some(result)
-        } else {
-            None
-        }
+        ThreeCheckState::from_fen(fen).map(Self::from_state)
     }
 
-    // broken for now
+    // repetitions and the 50 move rule are left to is_drawn, which needs the move history
     fn game_result(&self) -> super::board::GameResult {
-        let state = self.curr_state();
-        if state.check_count(state.stm()) >= 3 {
-            return GameResult::LOSS;
-        }
-
-        if self.gen_moves().len() == 0 {
-            if state.checkers().any() {
-                return GameResult::LOSS;
-            } else {
-                return GameResult::DRAW;
-            }
-        }
-
-        return GameResult::NONE;
+        self.state.game_result()
     }
 
     fn gen_moves(&self) -> Self::MoveList {
-        let mut moves = MoveList::new();
-        movegen::movegen(self.curr_state(), &mut moves);
-        moves
+        self.state.gen_moves()
     }
 
     fn make_move(&mut self, mv: Self::Move) -> bool {
-        self.states.push(self.curr_state().clone());
-        if mv == Move::NULL {
-            self.curr_state_mut().make_null_move();
+        let undo = if mv == Move::NULL {
+            self.state.make_null_move()
         } else {
-            self.curr_state_mut().make_move(mv);
-        }
-        self.keys.push(self.curr_state().zkey());
+            self.state.make_move(mv)
+        };
+        self.undos.push(undo);
+        self.keys.push(self.state.zkey());
         true
     }
 
     fn unmake_move(&mut self) {
-        self.states.pop();
+        let undo = self.undos.pop().unwrap();
+        self.state.unmake_move(&undo);
         self.keys.pop();
     }
 
     fn piece_on(&self, sq: Self::Square) -> Option<Self::Piece> {
-        self.curr_state().piece_at(sq)
+        self.state.piece_at(sq)
+    }
+}
+
+impl HashedBoard for ThreeCheckBoard {
+    fn hash_key(&self) -> u64 {
+        self.state.zkey().value()
+    }
+}
+
+// the old copy-make board, kept around to compare against
+pub type ThreeCheckCopyMakeBoard = CopyMakeWrapper<ThreeCheckState>;
+
+impl CopyMakeBoard for ThreeCheckState {
+    type Move = Move;
+    type Square = Square;
+    type Color = Color;
+    type Piece = Piece;
+    type MoveList = MoveList;
+
+    fn startpos() -> Self {
+        ThreeCheckState::startpos()
+    }
+
+    fn from_fen(fen: &str) -> Option<Self> {
+        ThreeCheckState::from_fen(fen)
+    }
+
+    fn game_result(&self) -> GameResult {
+        ThreeCheckState::game_result(self)
+    }
+
+    fn piece_on(&self, sq: Self::Square) -> Option<Self::Piece> {
+        self.piece_at(sq)
+    }
+
+    fn gen_moves(&self) -> Self::MoveList {
+        ThreeCheckState::gen_moves(self)
+    }
+
+    fn make_move(&mut self, mv: Self::Move) -> bool {
+        if mv == Move::NULL {
+            self.make_null_move();
+        } else {
+            ThreeCheckState::make_move(self, mv);
+        }
+        true
     }
 }
 
@@ -764,8 +1497,17 @@ impl fmt::Display for ThreeCheckState {
             }
         }
         writeln!(f, "half move clock: {}", self.half_move_clock)?;
-        writeln!(f, "White checks: {}", self.check_count(Color::White))?;
-        writeln!(f, "Black checks: {}", self.check_count(Color::Black))?;
+        writeln!(f, "variant: {}", self.variant)?;
+        match self.check_limit {
+            Some(limit) => {
+                writeln!(f, "check limit: {}", limit)?;
+                writeln!(f, "White checks: {}", self.check_count(Color::White))?;
+                writeln!(f, "Black checks: {}", self.check_count(Color::Black))?;
+            }
+            None => {
+                writeln!(f, "check limit: N/A")?;
+            }
+        }
         writeln!(f, "fen: {}", self.to_fen())?;
         Ok(())
     }
@@ -1,4 +1,4 @@
-use super::types::{Bitboard, Color, Square};
+use super::types::{Bitboard, Color, PieceType, Square};
 
 const KNIGHT_ATTACKS: [Bitboard; 64] = {
     let mut result = [Bitboard::NONE; 64];
@@ -462,3 +462,14 @@ pub fn rook_attacks(sq: Square, occ: Bitboard) -> Bitboard {
 pub fn queen_attacks(sq: Square, occ: Bitboard) -> Bitboard {
     rook_attacks(sq, occ) | bishop_attacks(sq, occ)
 }
+
+pub fn piece_attacks(pt: PieceType, c: Color, sq: Square, occ: Bitboard) -> Bitboard {
+    match pt {
+        PieceType::Pawn => pawn_attacks(c, sq),
+        PieceType::Knight => knight_attacks(sq),
+        PieceType::Bishop => bishop_attacks(sq, occ),
+        PieceType::Rook => rook_attacks(sq, occ),
+        PieceType::Queen => queen_attacks(sq, occ),
+        PieceType::King => king_attacks(sq),
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use super::pgn::PgnGame;
+use super::polyglot::polyglot_key;
+use super::zobrist::xorshift64;
+use super::{Color, Move, MoveKind, ThreeCheckState, Variant};
+
+// our own books start with this, plain polyglot books have no header at all
+const MAGIC: &[u8; 8] = b"calbook\0";
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 16;
+
+const CHECK_KEYS: usize = ThreeCheckState::MAX_CHECK_LIMIT as usize + 1;
+
+// mixed into polyglot keys so positions that only differ in the check counts get their own moves
+const BOOK_CHECK_KEYS: [[u64; CHECK_KEYS]; 2] = {
+    let mut result = [[0; CHECK_KEYS]; 2];
+    let mut rand = xorshift64(0x8C0A3E51);
+    let mut i = 0;
+    while i < 2 * CHECK_KEYS {
+        result[i / CHECK_KEYS][i % CHECK_KEYS] = rand;
+        rand = xorshift64(rand);
+        i += 1;
+    }
+    result
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookFormat {
+    Polyglot,
+    // polyglot entries, but keyed with check counts and tied to one variant and check limit
+    Variant {
+        variant: Variant,
+        check_limit: Option<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+// to square, from square and promotion piece laid out the same as polyglot. castles are
+// king takes rook like our own moves, king promotions only make sense in antichess books
+fn encode_move(mv: Move) -> u16 {
+    let promo = match mv.kind() {
+        // polyglot numbers knight to queen 1 to 4 like our piece types
+        MoveKind::Promotion => mv.promo_piece() as u16,
+        _ => 0,
+    };
+    mv.to_sq().value() | (mv.from_sq().value() << 6) | (promo << 12)
+}
+
+fn variant_from_raw(value: u8) -> Option<Variant> {
+    match value {
+        0 => Some(Variant::Standard),
+        1 => Some(Variant::KingOfTheHill),
+        2 => Some(Variant::Atomic),
+        3 => Some(Variant::Antichess),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Book {
+    format: BookFormat,
+    // sorted by key so all moves of a position are next to each other
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn new(format: BookFormat) -> Self {
+        Self {
+            format,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (format, body) = if bytes.starts_with(MAGIC) {
+            if bytes.len() < HEADER_SIZE {
+                return None;
+            }
+            let format = BookFormat::Variant {
+                variant: variant_from_raw(bytes[8])?,
+                check_limit: if bytes[9] == 0 { None } else { Some(bytes[9]) },
+            };
+            (format, &bytes[HEADER_SIZE..])
+        } else {
+            (BookFormat::Polyglot, bytes)
+        };
+        if body.len() % ENTRY_SIZE != 0 {
+            return None;
+        }
+
+        let mut entries: Vec<BookEntry> = body
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // probing relies on the order, and not every tool writes sorted books
+        entries.sort_by_key(|entry| entry.key);
+        Some(Self { format, entries })
+    }
+
+    pub fn from_file(filename: &str) -> Option<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename).ok()?.read_to_end(&mut bytes).ok()?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE);
+        if let BookFormat::Variant {
+            variant,
+            check_limit,
+        } = self.format
+        {
+            bytes.extend_from_slice(MAGIC);
+            bytes.push(variant as u8);
+            bytes.push(check_limit.unwrap_or(0));
+            bytes.resize(HEADER_SIZE, 0);
+        }
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.mv.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        File::create(filename)?.write_all(&self.to_bytes())
+    }
+
+    // None if the book is for a different variant or check limit
+    pub fn key(&self, state: &ThreeCheckState) -> Option<u64> {
+        match self.format {
+            BookFormat::Polyglot => Some(polyglot_key(state)),
+            BookFormat::Variant {
+                variant,
+                check_limit,
+            } => {
+                if variant != state.variant() || check_limit != state.check_limit() {
+                    return None;
+                }
+                let mut key = polyglot_key(state);
+                if check_limit.is_some() {
+                    for c in [Color::White, Color::Black] {
+                        key ^= BOOK_CHECK_KEYS[c as usize][state.check_count(c) as usize];
+                    }
+                }
+                Some(key)
+            }
+        }
+    }
+
+    // the legal book moves with their weights, entries that match no legal move are skipped
+    pub fn moves(&self, state: &ThreeCheckState) -> Vec<(Move, u16)> {
+        let Some(key) = self.key(state) else {
+            return Vec::new();
+        };
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal = state.gen_moves();
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = legal.iter().find(|mv| encode_move(**mv) == entry.mv)?;
+                Some((*mv, entry.weight))
+            })
+            .collect()
+    }
+
+    // picks a move with probability proportional to its weight
+    pub fn probe(&self, state: &ThreeCheckState) -> Option<Move> {
+        let moves = self.moves(state);
+        let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rand::random_range(0..total);
+        for (mv, weight) in moves {
+            if pick < weight as u32 {
+                return Some(mv);
+            }
+            pick -= weight as u32;
+        }
+        unreachable!()
+    }
+}
+
+// collects moves from games, weighting them 2 for a win and 1 for a draw by the side that
+// played them like polyglot make-book does
+pub struct BookBuilder {
+    book: Book,
+    max_ply: usize,
+    // moves played fewer times than this are left out
+    min_count: u32,
+    scores: HashMap<(u64, u16), (u32, u32)>,
+}
+
+impl BookBuilder {
+    pub fn new(format: BookFormat, max_ply: usize, min_count: u32) -> Self {
+        Self {
+            book: Book::new(format),
+            max_ply,
+            min_count,
+            scores: HashMap::new(),
+        }
+    }
+
+    // result is from white's point of view as in pgn, games in other variants are ignored
+    pub fn add_game(&mut self, start: &ThreeCheckState, moves: &[Move], result: &str) {
+        let win_score = |c: Color| match (result, c) {
+            ("1-0", Color::White) | ("0-1", Color::Black) => 2,
+            ("1/2-1/2", _) => 1,
+            _ => 0,
+        };
+        let mut state = start.clone();
+        for &mv in moves.iter().take(self.max_ply) {
+            let Some(key) = self.book.key(&state) else {
+                return;
+            };
+            let score = self.scores.entry((key, encode_move(mv))).or_insert((0, 0));
+            score.0 += 1;
+            score.1 += win_score(state.stm());
+            state.make_move(mv);
+        }
+    }
+
+    pub fn add_pgn_game(&mut self, game: &PgnGame) {
+        let moves: Vec<Move> = game.moves.iter().map(|mv| mv.mv).collect();
+        self.add_game(&game.start, &moves, &game.result);
+    }
+
+    pub fn build(self) -> Book {
+        let mut book = self.book;
+        let max_score = self
+            .scores
+            .values()
+            .map(|(_, score)| *score)
+            .max()
+            .unwrap_or(0);
+        // weights are scaled down only when they would not fit in 16 bits
+        let scale = (max_score as f64 / u16::MAX as f64).max(1.0);
+        book.entries = self
+            .scores
+            .into_iter()
+            .filter(|(_, (count, score))| *count >= self.min_count && *score > 0)
+            .map(|((key, mv), (_, score))| BookEntry {
+                key,
+                mv,
+                weight: ((score as f64 / scale) as u16).max(1),
+                learn: 0,
+            })
+            .collect();
+        book.entries
+            .sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        book
+    }
+}
@@ -85,6 +85,21 @@ impl CastlingRooks {
         rights
     }
 
+    // whether the castling rights can only be written with frc notation
+    pub fn requires_frc(&self, white_king: Square, black_king: Square) -> bool {
+        let mut result = false;
+        for (c, king) in [(Color::White, white_king), (Color::Black, black_king)] {
+            let rooks = self.color(c);
+            if rooks.king_side.is_none() && rooks.queen_side.is_none() {
+                continue;
+            }
+            result |= king.file() != 4;
+            result |= rooks.king_side.is_some_and(|sq| sq.file() != 7);
+            result |= rooks.queen_side.is_some_and(|sq| sq.file() != 0);
+        }
+        result
+    }
+
     pub const fn king_to(king_side: bool, c: Color) -> Square {
         [
             [Square::from_rank_file(0, 2), Square::from_rank_file(7, 2)],
@@ -103,7 +118,18 @@ impl CastlingRooks {
 impl fmt::Display for CastlingRooks {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.frc {
-            todo!()
+            // shredder fen
+            let rooks = [
+                (self.color(Color::White).king_side, 'A'),
+                (self.color(Color::White).queen_side, 'A'),
+                (self.color(Color::Black).king_side, 'a'),
+                (self.color(Color::Black).queen_side, 'a'),
+            ];
+            for (rook, base) in rooks {
+                if let Some(sq) = rook {
+                    write!(f, "{}", (base as u8 + sq.file()) as char)?;
+                }
+            }
         } else {
             if self.color(Color::White).king_side.is_some() {
                 write!(f, "K")?;
@@ -118,6 +144,9 @@ impl fmt::Display for CastlingRooks {
                 write!(f, "q")?;
             }
         }
+        if self.right_bits() == 0 {
+            write!(f, "-")?;
+        }
         Ok(())
     }
 }
@@ -1,10 +1,57 @@
-use super::types::{Bitboard, Color, Move, Piece, PieceType, Square};
-use super::{ThreeCheckState, attacks};
+use super::types::{Bitboard, Color, Move, Piece, PieceType, Square, Variant};
+use super::{CastlingRooks, ThreeCheckState, attacks};
 use arrayvec::ArrayVec;
 
 pub type MoveList = ArrayVec<Move, 256>;
 
-pub fn movegen(board: &ThreeCheckState, moves: &mut MoveList) {
+// noisy moves are captures and queen promotions, quiet moves are everything else.
+// noisy and quiet together make up all the legal moves
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenMode {
+    All,
+    Noisy,
+    Quiet,
+    QuietChecks,
+}
+
+impl GenMode {
+    fn includes(self, noisy: bool) -> bool {
+        match self {
+            GenMode::All => true,
+            GenMode::Noisy => noisy,
+            GenMode::Quiet | GenMode::QuietChecks => !noisy,
+        }
+    }
+
+    fn target_mask(self, board: &ThreeCheckState) -> Bitboard {
+        match self {
+            GenMode::All => Bitboard::ALL,
+            GenMode::Noisy => board.colors(board.stm().flip()),
+            GenMode::Quiet | GenMode::QuietChecks => !board.occ(),
+        }
+    }
+}
+
+pub fn movegen(board: &ThreeCheckState, mode: GenMode, moves: &mut MoveList) {
+    if mode == GenMode::QuietChecks {
+        let mut quiets = MoveList::new();
+        movegen(board, GenMode::Quiet, &mut quiets);
+        moves.extend(quiets.into_iter().filter(|mv| board.gives_check(*mv)));
+        return;
+    }
+
+    match board.variant() {
+        Variant::Atomic => {
+            gen_atomic(board, mode, moves);
+            return;
+        }
+        Variant::Antichess => {
+            gen_antichess(board, mode, moves);
+            return;
+        }
+        _ => {}
+    }
+
     let checkers = board.checkers();
     if !checkers.multiple() {
         let move_mask = !board.colors(board.stm())
@@ -13,16 +60,108 @@ pub fn movegen(board: &ThreeCheckState, moves: &mut MoveList) {
             } else {
                 Bitboard::ALL
             };
-        gen_pawn_moves(board, move_mask, moves);
-        gen_knight_moves(board, move_mask, moves);
-        gen_bishop_moves(board, move_mask, moves);
-        gen_rook_moves(board, move_mask, moves);
-        gen_queen_moves(board, move_mask, moves);
+        let target_mask = move_mask & mode.target_mask(board);
+        gen_pawn_moves(board, move_mask, mode, moves);
+        gen_knight_moves(board, target_mask, moves);
+        gen_bishop_moves(board, target_mask, moves);
+        gen_rook_moves(board, target_mask, moves);
+        gen_queen_moves(board, target_mask, moves);
+    }
+    gen_king_moves(board, mode, moves);
+}
+
+// whether a capture leaves the king safe depends on what the explosion removes,
+// so atomic moves are generated pseudo legally and filtered one at a time
+fn gen_atomic(board: &ThreeCheckState, mode: GenMode, moves: &mut MoveList) {
+    if board.king_exploded() {
+        return;
+    }
+
+    let mut pseudo_legal = MoveList::new();
+    let move_mask = !board.colors(board.stm());
+    let target_mask = move_mask & mode.target_mask(board);
+    gen_pawn_moves(board, move_mask, mode, &mut pseudo_legal);
+    gen_knight_moves(board, target_mask, &mut pseudo_legal);
+    gen_bishop_moves(board, target_mask, &mut pseudo_legal);
+    gen_rook_moves(board, target_mask, &mut pseudo_legal);
+    gen_queen_moves(board, target_mask, &mut pseudo_legal);
+
+    // kings cannot capture
+    if mode != GenMode::Noisy {
+        let sq = board.king_sq(board.stm());
+        let mut attacks = attacks::king_attacks(sq) & !board.occ();
+        while attacks.any() {
+            pseudo_legal.push(Move::normal(sq, attacks.poplsb()));
+        }
+    }
+
+    for mv in pseudo_legal {
+        if board.is_atomic_legal(mv) {
+            moves.push(mv);
+        }
+    }
+
+    if mode != GenMode::Noisy && board.checkers().empty() {
+        gen_castle(board, true, moves);
+        gen_castle(board, false, moves);
+    }
+}
+
+// kings are ordinary pieces and there is no check, but any capture is compulsory
+// the compulsory captures depend on every move, so this generates all of them and filters
+fn gen_antichess(board: &ThreeCheckState, mode: GenMode, moves: &mut MoveList) {
+    let mut pseudo_legal = MoveList::new();
+    let move_mask = !board.colors(board.stm());
+    gen_pawn_moves(board, move_mask, GenMode::All, &mut pseudo_legal);
+    gen_knight_moves(board, move_mask, &mut pseudo_legal);
+    gen_bishop_moves(board, move_mask, &mut pseudo_legal);
+    gen_rook_moves(board, move_mask, &mut pseudo_legal);
+    gen_queen_moves(board, move_mask, &mut pseudo_legal);
+
+    // promotions can leave several kings, or none at all
+    let mut kings = board.colored_pieces(Piece::new(board.stm(), PieceType::King));
+    while kings.any() {
+        let sq = kings.poplsb();
+        let mut attacks = attacks::king_attacks(sq) & move_mask;
+        while attacks.any() {
+            pseudo_legal.push(Move::normal(sq, attacks.poplsb()));
+        }
+    }
+
+    let is_capture = |mv: &Move| board.is_capture(*mv);
+    let forced = pseudo_legal.iter().any(is_capture);
+    moves.extend(
+        pseudo_legal
+            .into_iter()
+            .filter(|mv| (!forced || is_capture(mv)) && mode.includes(board.is_noisy(*mv))),
+    );
+}
+
+fn push_promos(
+    board: &ThreeCheckState,
+    from: Square,
+    to: Square,
+    mode: GenMode,
+    capture: bool,
+    moves: &mut MoveList,
+) {
+    for pt in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ] {
+        if pt == PieceType::King && board.variant() != Variant::Antichess {
+            continue;
+        }
+        if mode.includes(capture || pt == PieceType::Queen) {
+            moves.push(Move::promo(from, to, pt));
+        }
     }
-    gen_king_moves(board, moves);
 }
 
-fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut MoveList) {
+fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, mode: GenMode, moves: &mut MoveList) {
     let eighth_rank = if board.stm() == Color::White {
         Bitboard::LAST_RANK
     } else {
@@ -46,16 +185,23 @@ fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Move
         attacks::Direction::SouthEast
     };
 
-    let king_sq = board.king_sq(board.stm());
     let pawns = board.colored_pieces(Piece::new(board.stm(), PieceType::Pawn));
     let pinned = pawns & board.pinned();
     let unpinned = pawns ^ pinned;
+    // antichess may have no king, but then nothing is pinned either
+    let (pinned_pushers, pinned_west, pinned_east) = if pinned.any() {
+        let king_sq = board.king_sq(board.stm());
+        (
+            pinned & Bitboard::file(king_sq.file()),
+            pinned & attacks::ray_bb(king_sq, west_dir),
+            pinned & attacks::ray_bb(king_sq, east_dir),
+        )
+    } else {
+        (Bitboard::NONE, Bitboard::NONE, Bitboard::NONE)
+    };
 
     // the pinned file thingy probably be implemented better
-    let mut pushes = attacks::pawn_pushes_bb(
-        board.stm(),
-        unpinned | (pinned & Bitboard::file(king_sq.file())),
-    ) & !board.occ();
+    let mut pushes = attacks::pawn_pushes_bb(board.stm(), unpinned | pinned_pushers) & !board.occ();
     let mut double_pushes =
         attacks::pawn_pushes_bb(board.stm(), pushes & third_rank) & !board.occ() & move_mask;
 
@@ -66,27 +212,28 @@ fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Move
 
     while promo_pushes.any() {
         let sq = promo_pushes.poplsb();
-        moves.push(Move::promo(sq - push_offset, sq, PieceType::Knight));
-        moves.push(Move::promo(sq - push_offset, sq, PieceType::Bishop));
-        moves.push(Move::promo(sq - push_offset, sq, PieceType::Rook));
-        moves.push(Move::promo(sq - push_offset, sq, PieceType::Queen));
+        push_promos(board, sq - push_offset, sq, mode, false, moves);
     }
 
-    while non_promo_pushes.any() {
-        let sq = non_promo_pushes.poplsb();
-        moves.push(Move::normal(sq - push_offset, sq))
+    if mode.includes(false) {
+        while non_promo_pushes.any() {
+            let sq = non_promo_pushes.poplsb();
+            moves.push(Move::normal(sq - push_offset, sq))
+        }
+
+        while double_pushes.any() {
+            let sq = double_pushes.poplsb();
+            moves.push(Move::normal(sq - push_offset * 2, sq));
+        }
     }
 
-    while double_pushes.any() {
-        let sq = double_pushes.poplsb();
-        moves.push(Move::normal(sq - push_offset * 2, sq));
+    // everything past this point is a capture
+    if !mode.includes(true) {
+        return;
     }
 
     let mut west_caps = board.colors(board.stm().flip())
-        & attacks::pawn_west_attacks_bb(
-            board.stm(),
-            unpinned | (pinned & attacks::ray_bb(king_sq, west_dir)),
-        )
+        & attacks::pawn_west_attacks_bb(board.stm(), unpinned | pinned_west)
         & move_mask;
     let mut promo_west_caps = west_caps & eighth_rank;
     west_caps ^= promo_west_caps;
@@ -98,17 +245,11 @@ fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Move
 
     while promo_west_caps.any() {
         let sq = promo_west_caps.poplsb();
-        moves.push(Move::promo(sq - push_offset + 1, sq, PieceType::Knight));
-        moves.push(Move::promo(sq - push_offset + 1, sq, PieceType::Bishop));
-        moves.push(Move::promo(sq - push_offset + 1, sq, PieceType::Rook));
-        moves.push(Move::promo(sq - push_offset + 1, sq, PieceType::Queen));
+        push_promos(board, sq - push_offset + 1, sq, mode, true, moves);
     }
 
     let mut east_caps = board.colors(board.stm().flip())
-        & attacks::pawn_east_attacks_bb(
-            board.stm(),
-            unpinned | (pinned & attacks::ray_bb(king_sq, east_dir)),
-        )
+        & attacks::pawn_east_attacks_bb(board.stm(), unpinned | pinned_east)
         & move_mask;
     let mut promo_east_caps = east_caps & eighth_rank;
     east_caps ^= promo_east_caps;
@@ -120,10 +261,7 @@ fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Move
 
     while promo_east_caps.any() {
         let sq = promo_east_caps.poplsb();
-        moves.push(Move::promo(sq - push_offset - 1, sq, PieceType::Knight));
-        moves.push(Move::promo(sq - push_offset - 1, sq, PieceType::Bishop));
-        moves.push(Move::promo(sq - push_offset - 1, sq, PieceType::Rook));
-        moves.push(Move::promo(sq - push_offset - 1, sq, PieceType::Queen));
+        push_promos(board, sq - push_offset - 1, sq, mode, true, moves);
     }
 
     if let Some(ep_square) = board.ep_square() {
@@ -141,8 +279,10 @@ fn gen_pawn_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Move
             let diags = board.colored_pieces(Piece::new(board.stm().flip(), PieceType::Bishop))
                 | board.colored_pieces(Piece::new(board.stm().flip(), PieceType::Queen));
 
-            if (attacks::rook_attacks(king_sq, ep_occ) & hvs).empty()
-                && (attacks::bishop_attacks(king_sq, ep_occ) & diags).empty()
+            // atomic checks every move for legality later on and antichess has no check
+            if matches!(board.variant(), Variant::Atomic | Variant::Antichess)
+                || (attacks::rook_attacks(board.king_sq(board.stm()), ep_occ) & hvs).empty()
+                    && (attacks::bishop_attacks(board.king_sq(board.stm()), ep_occ) & diags).empty()
             {
                 moves.push(Move::enpassant(from, ep_square));
             }
@@ -212,10 +352,10 @@ fn gen_queen_moves(board: &ThreeCheckState, move_mask: Bitboard, moves: &mut Mov
     }
 }
 
-fn gen_king_moves(board: &ThreeCheckState, moves: &mut MoveList) {
+fn gen_king_moves(board: &ThreeCheckState, mode: GenMode, moves: &mut MoveList) {
     let sq = board.king_sq(board.stm());
     let mut attacks = attacks::king_attacks(sq);
-    attacks &= !board.colors(board.stm());
+    attacks &= !board.colors(board.stm()) & mode.target_mask(board);
     while attacks.any() {
         let dst = attacks.poplsb();
         if !board.attacked_by(dst, board.stm().flip()) {
@@ -223,73 +363,55 @@ fn gen_king_moves(board: &ThreeCheckState, moves: &mut MoveList) {
         }
     }
 
-    if board.checkers().any() {
+    if mode == GenMode::Noisy || board.checkers().any() {
         return;
     }
 
-    if board
-        .castling_rooks()
-        .color(board.stm())
-        .king_side
-        .is_some()
-    {
-        let king_dst = if board.stm() == Color::White {
-            Square::from_rank_file(0, 6)
-        } else {
-            Square::from_rank_file(7, 6)
-        };
-        let rook_dst = if board.stm() == Color::White {
-            Square::from_rank_file(0, 5)
-        } else {
-            Square::from_rank_file(7, 5)
-        };
-
-        let rook_sq = board.castling_rooks().color(board.stm()).king_side.unwrap();
-
-        let block_squares =
-            attacks::line_between(sq, king_dst) | attacks::line_between(rook_sq, rook_dst);
-
-        let check_squares = attacks::line_between(sq, king_dst) | Bitboard::from_square(king_dst);
-
-        if (board.occ() & block_squares).empty() {
-            if !board.any_attacked_by(check_squares, board.stm().flip()) {
-                moves.push(Move::castle(sq, rook_sq));
-            }
-        }
+    gen_castle(board, true, moves);
+    gen_castle(board, false, moves);
+}
+
+// written to handle frc, where the king and rook can start anywhere on the back rank
+pub(super) fn castle_move(board: &ThreeCheckState, king_side: bool) -> Option<Move> {
+    let rooks = board.castling_rooks();
+    let rook_sq = if king_side {
+        rooks.color(board.stm()).king_side
+    } else {
+        rooks.color(board.stm()).queen_side
+    };
+    let rook_sq = rook_sq?;
+
+    let king_sq = board.king_sq(board.stm());
+    let king_dst = CastlingRooks::king_to(king_side, board.stm());
+    let rook_dst = CastlingRooks::rook_to(king_side, board.stm());
+    let king_bb = Bitboard::from_square(king_sq);
+    let rook_bb = Bitboard::from_square(rook_sq);
+
+    let king_path = attacks::line_between(king_sq, king_dst) | Bitboard::from_square(king_dst);
+    let rook_path = attacks::line_between(rook_sq, rook_dst) | Bitboard::from_square(rook_dst);
+    let block_squares = (king_path | rook_path) & !(king_bb | rook_bb);
+    if (board.occ() & block_squares).any() {
+        return None;
     }
 
-    if board
-        .castling_rooks()
-        .color(board.stm())
-        .queen_side
-        .is_some()
-    {
-        let king_dst = if board.stm() == Color::White {
-            Square::from_rank_file(0, 2)
-        } else {
-            Square::from_rank_file(7, 2)
-        };
-        let rook_dst = if board.stm() == Color::White {
-            Square::from_rank_file(0, 3)
-        } else {
-            Square::from_rank_file(7, 3)
-        };
-
-        let rook_sq = board
-            .castling_rooks()
-            .color(board.stm())
-            .queen_side
-            .unwrap();
-
-        let block_squares =
-            attacks::line_between(sq, king_dst) | attacks::line_between(rook_sq, rook_dst);
-
-        let check_squares = attacks::line_between(sq, king_dst) | Bitboard::from_square(king_dst);
-
-        if (board.occ() & block_squares).empty() {
-            if !board.any_attacked_by(check_squares, board.stm().flip()) {
-                moves.push(Move::castle(sq, rook_sq));
-            }
+    // the castling rook may be the only thing shielding the king's destination
+    let occ = board.occ() ^ king_bb ^ rook_bb;
+    let mut check_squares = king_path;
+    // in atomic the king is safe next to the enemy king
+    if board.variant() == Variant::Atomic {
+        check_squares &= !attacks::king_attacks(board.king_sq(board.stm().flip()));
+    }
+    while check_squares.any() {
+        if board.attacked_by_occ(check_squares.poplsb(), board.stm().flip(), occ) {
+            return None;
         }
     }
+
+    Some(Move::castle(king_sq, rook_sq))
+}
+
+fn gen_castle(board: &ThreeCheckState, king_side: bool, moves: &mut MoveList) {
+    if let Some(mv) = castle_move(board, king_side) {
+        moves.push(mv);
+    }
 }
@@ -0,0 +1,644 @@
+use std::fmt;
+use std::io::BufRead;
+
+use crate::games::board::Board;
+
+use super::san::{parse_san, to_san};
+use super::{Color, Move, ThreeCheckBoard, ThreeCheckState, Variant};
+
+#[derive(Debug, Clone)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    // alternatives to this move, each starting from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> Self {
+        Self {
+            mv: mv,
+            nags: Vec::new(),
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: ThreeCheckState,
+    pub start_move_number: u32,
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+// limits other than three checks and frc outside of standard chess go into CheckLimit and FRC tags
+fn variant_name(state: &ThreeCheckState) -> &'static str {
+    match (state.variant(), state.check_limit()) {
+        (Variant::KingOfTheHill, _) => "King of the Hill",
+        (Variant::Atomic, _) => "Atomic",
+        (Variant::Antichess, _) => "Antichess",
+        (Variant::Standard, Some(_)) => "Three-check",
+        (Variant::Standard, None) if state.is_frc() => "Chess960",
+        (Variant::Standard, None) => "Standard",
+    }
+}
+
+fn check_limit_tag(state: &ThreeCheckState) -> Option<u8> {
+    match (state.variant(), state.check_limit()) {
+        (Variant::Standard, Some(3)) => None,
+        (_, limit) => limit,
+    }
+}
+
+// the check limit, variant and whether castling is frc for a Variant tag
+fn parse_variant(name: &str) -> Option<(Option<u8>, Variant, bool)> {
+    let name = name.to_ascii_lowercase().replace([' ', '-'], "");
+    match name.as_str() {
+        "" | "standard" | "fromposition" => Some((None, Variant::Standard, false)),
+        "threecheck" | "3check" => Some((Some(3), Variant::Standard, false)),
+        "kingofthehill" | "koth" => Some((None, Variant::KingOfTheHill, false)),
+        "atomic" => Some((None, Variant::Atomic, false)),
+        "antichess" | "giveaway" | "suicide" => Some((None, Variant::Antichess, false)),
+        "chess960" | "fischerandom" | "fischerrandom" => Some((None, Variant::Standard, true)),
+        _ => None,
+    }
+}
+
+impl PgnGame {
+    pub fn new(start: ThreeCheckState) -> Self {
+        let mut game = Self {
+            tags: Vec::new(),
+            start: start,
+            start_move_number: 1,
+            comment: None,
+            moves: Vec::new(),
+            result: "*".to_string(),
+        };
+        for name in ["Event", "Site", "Date", "Round", "White", "Black"] {
+            game.set_tag(name, "?");
+        }
+        game.set_tag("Result", "*");
+        game
+    }
+
+    pub fn from_moves(start: ThreeCheckState, moves: &[Move], result: &str) -> Self {
+        let mut game = Self::new(start);
+        game.moves = moves.iter().map(|mv| PgnMove::new(*mv)).collect();
+        game.result = result.to_string();
+        game.set_tag("Result", result);
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // the board after the main line
+    pub fn board(&self) -> ThreeCheckBoard {
+        let mut board = ThreeCheckBoard::from_state(self.start.clone());
+        for mv in self.moves.iter() {
+            board.make_move(mv.mv);
+        }
+        board
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut tags = self.tags.clone();
+        let variant = variant_name(&self.start);
+        let mut set = |name: &str, value: String| match tags.iter_mut().find(|(t, _)| t == name) {
+            Some(tag) => tag.1 = value,
+            None => tags.push((name.to_string(), value)),
+        };
+        set("Result", self.result.clone());
+        if variant != "Standard" {
+            set("Variant", variant.to_string());
+        }
+        let fen = self.start.to_fen();
+        let startpos =
+            ThreeCheckState::startpos_with_options(self.start.check_limit(), self.start.variant());
+        if fen != startpos.to_fen() || self.start_move_number != 1 || self.start.is_frc() {
+            // to_fen always writes move 1
+            let fen = format!(
+                "{} {}",
+                fen.trim_end().trim_end_matches(char::is_numeric).trim_end(),
+                self.start_move_number
+            );
+            set("SetUp", "1".to_string());
+            set("FEN", fen);
+        }
+        // parsed games may carry tags for a different start
+        tags.retain(|(name, _)| name != "CheckLimit" && name != "FRC");
+        if let Some(limit) = check_limit_tag(&self.start) {
+            tags.push(("CheckLimit".to_string(), limit.to_string()));
+        }
+        if self.start.is_frc() && variant != "Chess960" {
+            tags.push(("FRC".to_string(), "1".to_string()));
+        }
+
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn += format!("[{} \"{}\"]\n", name, value).as_str();
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        let mut board = ThreeCheckBoard::from_state(self.start.clone());
+        write_line(&mut board, &self.moves, self.start_move_number, &mut tokens);
+        tokens.push(self.result.clone());
+
+        // wrap movetext at 80 columns
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 80 {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn += token.as_str();
+        }
+        pgn += "\n\n";
+        pgn
+    }
+}
+
+// number is the move number of the first move in the line
+fn write_line(
+    board: &mut ThreeCheckBoard,
+    moves: &[PgnMove],
+    mut number: u32,
+    tokens: &mut Vec<String>,
+) {
+    // the move number is repeated for black after anything interrupting the movetext
+    let mut need_number = true;
+    for mv in moves.iter() {
+        let stm = board.curr_state().stm();
+        let san = to_san(board.curr_state(), mv.mv);
+        match stm {
+            Color::White => tokens.push(format!("{}. {}", number, san)),
+            Color::Black if need_number => tokens.push(format!("{}... {}", number, san)),
+            Color::Black => tokens.push(san),
+        }
+        need_number = false;
+
+        for nag in mv.nags.iter() {
+            tokens.push(format!("${}", nag));
+        }
+        if let Some(comment) = &mv.comment {
+            tokens.push(format!("{{{}}}", comment));
+            need_number = true;
+        }
+
+        for variation in mv.variations.iter() {
+            let mut var_tokens = Vec::new();
+            write_line(&mut board.clone(), variation, number, &mut var_tokens);
+            if let Some(first) = var_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = var_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.extend(var_tokens);
+            need_number = true;
+        }
+
+        board.make_move(mv.mv);
+        if stm == Color::Black {
+            number += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(String),
+    San(String),
+}
+
+struct Tokenizer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str, first_line: usize) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: first_line,
+            column: 1,
+        }
+    }
+
+    fn error(&self, message: String) -> PgnError {
+        PgnError {
+            line: self.line,
+            column: self.column,
+            message: message,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn read_until(&mut self, end: char) -> Result<String, PgnError> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == end => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(self.error(format!("missing '{}'", end))),
+            }
+        }
+    }
+
+    fn read_tag(&mut self) -> Result<Token, PgnError> {
+        let inner = self.read_until(']')?;
+        let inner = inner.trim();
+        let Some((name, value)) = inner.split_once(char::is_whitespace) else {
+            return Err(self.error(format!("bad tag [{}]", inner)));
+        };
+        let value = value.trim();
+        if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+            return Err(self.error(format!("bad tag value {}", value)));
+        }
+        let value = value[1..value.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\");
+        Ok(Token::Tag(name.to_string(), value))
+    }
+
+    // returns the token with the position it started at
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        loop {
+            while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+                self.bump();
+            }
+            let (line, column) = (self.line, self.column);
+            let Some(c) = self.bump() else {
+                return Ok(None);
+            };
+            let token = match c {
+                '[' => self.read_tag()?,
+                '{' => Token::Comment(self.read_until('}')?.trim().to_string()),
+                ';' => Token::Comment(self.read_until('\n').unwrap_or_default().trim().to_string()),
+                // escaped lines are ignored
+                '%' if column == 1 => {
+                    let _ = self.read_until('\n');
+                    continue;
+                }
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '$' => {
+                    let mut digits = String::new();
+                    while self.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        digits.push(self.bump().unwrap());
+                    }
+                    match digits.parse::<u8>() {
+                        Ok(nag) => Token::Nag(nag),
+                        Err(_) => return Err(self.error(format!("bad nag ${}", digits))),
+                    }
+                }
+                _ => {
+                    let mut word = c.to_string();
+                    while self
+                        .chars
+                        .peek()
+                        .is_some_and(|c| !c.is_whitespace() && !"[]{}();$".contains(*c))
+                    {
+                        word.push(self.bump().unwrap());
+                    }
+                    match word_token(&word) {
+                        Some(token) => token,
+                        None => continue,
+                    }
+                }
+            };
+            return Ok(Some((token, line, column)));
+        }
+    }
+}
+
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+// move numbers are dropped, anything else that is not a result is a move
+fn word_token(word: &str) -> Option<Token> {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word) {
+        return Some(Token::Result(word.to_string()));
+    }
+    if let Some(nag) = suffix_nag(word) {
+        return Some(Token::Nag(nag));
+    }
+    if word.starts_with("0-0") {
+        return Some(Token::San(word.to_string()));
+    }
+    let san = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = san.trim_start_matches('.');
+    if san.is_empty() {
+        return None;
+    }
+    Some(Token::San(san.to_string()))
+}
+
+// parses a single game, first_line is where the game starts in the file
+pub fn parse_game(text: &str, first_line: usize) -> Result<PgnGame, PgnError> {
+    let mut tokenizer = Tokenizer::new(text, first_line);
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next_token()? {
+        tokens.push(token);
+    }
+
+    let mut tags = Vec::new();
+    let mut rest = tokens.as_slice();
+    while let Some(((Token::Tag(name, value), _, _), tail)) = rest.split_first() {
+        tags.push((name.clone(), value.clone()));
+        rest = tail;
+    }
+
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let error = |message: String| PgnError {
+        line: first_line,
+        column: 1,
+        message: message,
+    };
+
+    let Some((mut check_limit, variant, mut frc)) = parse_variant(tag("Variant").unwrap_or(""))
+    else {
+        return Err(error(format!(
+            "unsupported variant {}",
+            tag("Variant").unwrap()
+        )));
+    };
+    if let Some(limit) = tag("CheckLimit") {
+        match limit.parse::<u8>() {
+            Ok(limit) if (1..=ThreeCheckState::MAX_CHECK_LIMIT).contains(&limit) => {
+                check_limit = Some(limit)
+            }
+            _ => return Err(error(format!("bad check limit {}", limit))),
+        }
+    }
+    frc |= tag("FRC") == Some("1");
+    let (mut start, start_move_number) = match tag("FEN") {
+        Some(fen) => {
+            let state = ThreeCheckState::from_fen_with_options(fen, check_limit, variant)
+                .ok_or_else(|| error(format!("bad fen {}", fen)))?;
+            let number = fen
+                .split_whitespace()
+                .last()
+                .and_then(|n| n.parse::<u32>().ok())
+                .unwrap_or(1);
+            (state, number.max(1))
+        }
+        None => (
+            ThreeCheckState::startpos_with_options(check_limit, variant),
+            1,
+        ),
+    };
+    if frc {
+        start.enable_frc();
+    }
+
+    let mut game = PgnGame {
+        tags: tags.clone(),
+        start: start.clone(),
+        start_move_number: start_move_number,
+        comment: None,
+        moves: Vec::new(),
+        result: tag("Result").unwrap_or("*").to_string(),
+    };
+
+    if let Some(((Token::Comment(comment), _, _), tail)) = rest.split_first() {
+        game.comment = Some(comment.clone());
+        rest = tail;
+    }
+
+    let mut board = ThreeCheckBoard::from_state(start);
+    let mut pos = 0;
+    game.moves = parse_line(&mut board, rest, &mut pos, false)?;
+    if let Some((Token::Result(result), _, _)) = rest.get(pos) {
+        game.result = result.clone();
+        pos += 1;
+    }
+    if let Some((token, line, column)) = rest.get(pos) {
+        return Err(PgnError {
+            line: *line,
+            column: *column,
+            message: format!("unexpected {:?} after the result", token),
+        });
+    }
+    Ok(game)
+}
+
+fn parse_line(
+    board: &mut ThreeCheckBoard,
+    tokens: &[(Token, usize, usize)],
+    pos: &mut usize,
+    variation: bool,
+) -> Result<Vec<PgnMove>, PgnError> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut made = 0;
+    while let Some((token, line, column)) = tokens.get(*pos) {
+        let err = |message: String| PgnError {
+            line: *line,
+            column: *column,
+            message: message,
+        };
+        match token {
+            Token::San(text) => {
+                let san_end = text.find(['!', '?']).unwrap_or(text.len());
+                let Some(mv) = parse_san(board.curr_state(), &text[..san_end]) else {
+                    return Err(err(format!("illegal move {}", text)));
+                };
+                let mut pgn_move = PgnMove::new(mv);
+                pgn_move.nags.extend(suffix_nag(&text[san_end..]));
+                moves.push(pgn_move);
+                board.make_move(mv);
+                made += 1;
+            }
+            Token::Nag(nag) => match moves.last_mut() {
+                Some(mv) => mv.nags.push(*nag),
+                None => return Err(err(format!("${} before any move", nag))),
+            },
+            Token::Comment(comment) => match moves.last_mut() {
+                Some(mv) => match &mut mv.comment {
+                    Some(existing) => *existing += format!(" {}", comment).as_str(),
+                    None => mv.comment = Some(comment.clone()),
+                },
+                // a comment leading a variation has nowhere else to go
+                None => {}
+            },
+            Token::Open => {
+                if moves.is_empty() {
+                    return Err(err("variation before any move".to_string()));
+                }
+                // variations replace the last move
+                board.unmake_move();
+                *pos += 1;
+                let var = parse_line(board, tokens, pos, true)?;
+                board.make_move(moves.last().unwrap().mv);
+                moves.last_mut().unwrap().variations.push(var);
+            }
+            Token::Close => {
+                if !variation {
+                    return Err(err("unmatched ')'".to_string()));
+                }
+                for _ in 0..made {
+                    board.unmake_move();
+                }
+                return Ok(moves);
+            }
+            Token::Result(_) => {
+                if variation {
+                    return Err(err("result inside a variation".to_string()));
+                }
+                return Ok(moves);
+            }
+            Token::Tag(_, _) => return Err(err("tag inside movetext".to_string())),
+        }
+        *pos += 1;
+    }
+    if variation {
+        let (line, column) = tokens.last().map_or((0, 0), |(_, l, c)| (*l, *c));
+        return Err(PgnError {
+            line: line,
+            column: column,
+            message: "unterminated variation".to_string(),
+        });
+    }
+    Ok(moves)
+}
+
+// streams games from a reader one at a time, so large databases never have to be in memory at once
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    line: usize,
+    // the first line of the next game, read while looking for the end of the last one
+    pending: Option<(usize, String)>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: reader,
+            line: 0,
+            pending: None,
+        }
+    }
+
+    fn read_line(&mut self) -> Option<(usize, String)> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                self.line += 1;
+                Some((self.line, line))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text = String::new();
+        let mut first_line = 0;
+        let mut in_movetext = false;
+        let mut comment_depth = 0;
+        while let Some((line_number, line)) = self.read_line() {
+            let trimmed = line.trim_start();
+            // a tag after movetext starts the next game
+            if in_movetext && comment_depth == 0 && trimmed.starts_with('[') {
+                self.pending = Some((line_number, line));
+                return Some(parse_game(&text, first_line));
+            }
+            if text.trim().is_empty() {
+                // blank lines before the game are kept so line numbers stay right
+                first_line = line_number - text.lines().count();
+            }
+            if comment_depth == 0 && !trimmed.is_empty() && !trimmed.starts_with('[') {
+                in_movetext = true;
+            }
+            for c in line.chars() {
+                match c {
+                    '{' => comment_depth += 1,
+                    '}' if comment_depth > 0 => comment_depth -= 1,
+                    _ => {}
+                }
+            }
+            text += line.as_str();
+        }
+        if text.trim().is_empty() {
+            return None;
+        }
+        Some(parse_game(&text, first_line))
+    }
+}
@@ -0,0 +1,181 @@
+use super::movegen::MoveList;
+use super::types::{Move, MoveKind, Piece, PieceType, Square, sq_from_str};
+use super::{Color, ThreeCheckState};
+
+fn piece_letter(pt: PieceType) -> char {
+    Piece::new(Color::White, pt).char_repr()
+}
+
+fn piece_from_letter(c: char) -> Option<PieceType> {
+    match c {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+// the san of a move without the check or mate suffix
+fn san_body(state: &ThreeCheckState, mv: Move, moves: &MoveList) -> String {
+    let from = mv.from_sq();
+    let to = mv.to_sq();
+    if mv.kind() == MoveKind::Castle {
+        // castles are stored king takes rook, so this works for frc as well
+        return if to > from { "O-O" } else { "O-O-O" }.to_string();
+    }
+
+    let pt = state.piece_at(from).unwrap().piece_type();
+    let capture = state.is_capture(mv);
+    let mut san = String::new();
+    if pt == PieceType::Pawn {
+        if capture {
+            san.push((b'a' + from.file()) as char);
+        }
+    } else {
+        san.push(piece_letter(pt));
+
+        // other pieces of the same type that can reach the same square
+        let others: Vec<Square> = moves
+            .iter()
+            .filter(|other| {
+                other.to_sq() == to
+                    && other.from_sq() != from
+                    && other.kind() != MoveKind::Castle
+                    && state.piece_at(other.from_sq()).unwrap().piece_type() == pt
+            })
+            .map(|other| other.from_sq())
+            .collect();
+        if !others.is_empty() {
+            if others.iter().all(|sq| sq.file() != from.file()) {
+                san.push((b'a' + from.file()) as char);
+            } else if others.iter().all(|sq| sq.rank() != from.rank()) {
+                san.push((b'1' + from.rank()) as char);
+            } else {
+                san += from.to_string().as_str();
+            }
+        }
+    }
+
+    if capture {
+        san.push('x');
+    }
+    san += to.to_string().as_str();
+
+    if mv.kind() == MoveKind::Promotion {
+        san.push('=');
+        san.push(piece_letter(mv.promo_piece()));
+    }
+    san
+}
+
+// mv has to be legal in state
+pub fn to_san(state: &ThreeCheckState, mv: Move) -> String {
+    let mut san = san_body(state, mv, &state.gen_moves());
+
+    let mut next = state.clone();
+    next.make_move(mv);
+    if next.checkers().any() {
+        san.push(if next.gen_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+// accepts missing or extra disambiguation, a missing 'x' or '=', long algebraic with '-',
+// annotations, 0-0 style castles and falls back to uci notation
+pub fn parse_san(state: &ThreeCheckState, san: &str) -> Option<Move> {
+    let moves = state.gen_moves();
+    let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    if let Some(mv) = moves
+        .iter()
+        .find(|mv| san_body(state, **mv, &moves) == trimmed)
+    {
+        return Some(*mv);
+    }
+
+    let castle = trimmed.replace('0', "O").to_ascii_uppercase();
+    if castle == "O-O" || castle == "O-O-O" {
+        let king_side = castle == "O-O";
+        return moves
+            .iter()
+            .copied()
+            .find(|mv| mv.kind() == MoveKind::Castle && (mv.to_sq() > mv.from_sq()) == king_side);
+    }
+
+    if let Some(mv) = moves
+        .iter()
+        .find(|mv| mv.to_uci(state.is_frc()) == trimmed.to_ascii_lowercase())
+    {
+        return Some(*mv);
+    }
+
+    let mut chars: Vec<char> = trimmed
+        .chars()
+        .filter(|c| !matches!(c, 'x' | ':' | '=' | '-'))
+        .collect();
+
+    let promo = match chars.last().copied() {
+        Some(c) if c.is_ascii_alphabetic() && !('a'..='h').contains(&c) => {
+            chars.pop();
+            Some(piece_from_letter(c.to_ascii_uppercase())?)
+        }
+        _ => None,
+    };
+
+    let pt = match chars.first().copied() {
+        Some('P') => {
+            chars.remove(0);
+            PieceType::Pawn
+        }
+        Some(c) => match piece_from_letter(c) {
+            Some(pt) => {
+                chars.remove(0);
+                pt
+            }
+            None => PieceType::Pawn,
+        },
+        None => return None,
+    };
+
+    if chars.len() < 2 {
+        return None;
+    }
+    let to_str: String = chars.split_off(chars.len() - 2).into_iter().collect();
+    let to = sq_from_str(&to_str).ok()?;
+
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in chars {
+        match c {
+            'a'..='h' => from_file = Some(c as u8 - b'a'),
+            '1'..='8' => from_rank = Some(c as u8 - b'1'),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = moves.iter().copied().filter(|mv| {
+        mv.kind() != MoveKind::Castle
+            && mv.to_sq() == to
+            && state.piece_at(mv.from_sq()).unwrap().piece_type() == pt
+            && from_file.is_none_or(|file| mv.from_sq().file() == file)
+            && from_rank.is_none_or(|rank| mv.from_sq().rank() == rank)
+            && match promo {
+                Some(promo) => mv.kind() == MoveKind::Promotion && mv.promo_piece() == promo,
+                None => mv.kind() != MoveKind::Promotion,
+            }
+    });
+    let mv = candidates.next()?;
+    if candidates.next().is_some() {
+        return None;
+    }
+    Some(mv)
+}
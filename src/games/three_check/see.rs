@@ -1,4 +1,4 @@
-use super::{Bitboard, Color, Move, MoveKind, Piece, PieceType, ThreeCheckState, attacks};
+use super::{Bitboard, Color, Move, MoveKind, PieceType, ThreeCheckState, Variant, attacks};
 
 fn see_piece_value(pt: PieceType) -> i32 {
     const VALUES: [i32; 6] = [100, 450, 450, 650, 1350, 0];
@@ -21,8 +21,45 @@ fn pop_least_valuable(
     None
 }
 
+// the capturing piece blows up with everything else, so there is nothing to exchange
+// and a capture is only worth the difference in material destroyed
+fn see_atomic(state: &ThreeCheckState, mv: Move, threshold: i32) -> bool {
+    let to = mv.to_sq();
+    let cap_sq = match mv.kind() {
+        MoveKind::Castle => return threshold <= 0,
+        MoveKind::Enpassant if state.stm() == Color::White => to - 8,
+        MoveKind::Enpassant => to + 8,
+        _ => to,
+    };
+    if state.piece_at(cap_sq).is_none() {
+        return threshold <= 0;
+    }
+
+    let mut blast = (attacks::king_attacks(to) & state.occ() & !state.pieces(PieceType::Pawn))
+        | Bitboard::from_square(mv.from_sq())
+        | Bitboard::from_square(cap_sq);
+
+    let mut score = 0;
+    while blast.any() {
+        let piece = state.piece_at(blast.poplsb()).unwrap();
+        if piece.piece_type() == PieceType::King {
+            return piece.color() != state.stm();
+        }
+        if piece.color() == state.stm() {
+            score -= see_piece_value(piece.piece_type());
+        } else {
+            score += see_piece_value(piece.piece_type());
+        }
+    }
+    score >= threshold
+}
+
 // yoinked from stormphrax
 pub fn see(state: &ThreeCheckState, mv: Move, threshold: i32) -> bool {
+    if state.variant() == Variant::Atomic {
+        return see_atomic(state, mv, threshold);
+    }
+
     if mv.kind() != MoveKind::None {
         return true;
     }
@@ -0,0 +1,146 @@
+use std::fs;
+
+use super::{GenMode, Move, PieceType, Square, ThreeCheckBoard, ThreeCheckState, Variant};
+use crate::games::board::{Board, GameResult};
+
+// every move the encoding can express, whether or not it makes any sense
+fn all_moves() -> Vec<Move> {
+    let mut moves = Vec::new();
+    for from in 0..64 {
+        for to in 0..64 {
+            let from = Square::from_raw(from);
+            let to = Square::from_raw(to);
+            moves.push(Move::normal(from, to));
+            moves.push(Move::castle(from, to));
+            moves.push(Move::enpassant(from, to));
+            for pt in [
+                PieceType::Knight,
+                PieceType::Bishop,
+                PieceType::Rook,
+                PieceType::Queen,
+                PieceType::King,
+            ] {
+                moves.push(Move::promo(from, to, pt));
+            }
+        }
+    }
+    moves
+}
+
+// counts the moves where is_legal, gives_check or unmake_move disagree with full movegen
+// in the current position, plus one if the noisy, quiet and quiet check modes do not add up
+fn check_position(board: &mut ThreeCheckBoard, candidates: &[Move]) -> u32 {
+    let legal = board.gen_moves();
+    let mut errors = 0;
+    let before = board.curr_state().clone();
+    for &mv in legal.iter() {
+        board.make_move(mv);
+        if board.curr_state().checkers().any() != before.gives_check(mv) {
+            errors += 1;
+            println!("gives_check mismatch: {} move {}", before.to_fen(), mv);
+        }
+        board.unmake_move();
+        let after = board.curr_state();
+        if after.to_fen() != before.to_fen()
+            || after.zkey() != before.zkey()
+            || after.pawn_key() != before.pawn_key()
+            || after.checkers() != before.checkers()
+            || after.pinned() != before.pinned()
+        {
+            errors += 1;
+            println!("Unmake mismatch: {} move {}", before.to_fen(), mv);
+        }
+    }
+
+    let state = board.curr_state();
+    let noisy = state.gen_moves_mode(GenMode::Noisy);
+    let quiet = state.gen_moves_mode(GenMode::Quiet);
+    let quiet_checks = state.gen_moves_mode(GenMode::QuietChecks);
+    let split_ok = noisy.len() + quiet.len() == legal.len()
+        && legal
+            .iter()
+            .all(|mv| noisy.contains(mv) != quiet.contains(mv))
+        && noisy.iter().all(|mv| state.is_noisy(*mv))
+        && quiet.iter().all(|mv| !state.is_noisy(*mv))
+        && quiet.iter().all(|mv| {
+            let mut next = state.clone();
+            next.make_move(*mv);
+            next.checkers().any() == quiet_checks.contains(mv)
+        });
+    if !split_ok {
+        errors += 1;
+        println!("Movegen modes do not add up: {}", state.to_fen());
+    }
+
+    for &mv in candidates {
+        let expected = legal.contains(&mv);
+        let pseudolegal = state.is_pseudolegal(mv);
+        let is_legal = state.is_legal(mv);
+        if expected != is_legal || (expected && !pseudolegal) {
+            errors += 1;
+            println!(
+                "Mismatch: {} move {} {:?} movegen {} is_pseudolegal {} is_legal {}",
+                state.to_fen(),
+                mv,
+                mv.kind(),
+                expected,
+                pseudolegal,
+                is_legal
+            );
+        }
+    }
+    errors
+}
+
+// plays random games from every position of a perft suite and checks is_pseudolegal and
+// is_legal against gen_moves for every possible move encoding along the way
+fn check_suite_file(
+    filename: &str,
+    check_limit: Option<u8>,
+    variant: Variant,
+    games: u32,
+    plies: u32,
+) {
+    let path = format!("{}/res/{}", env!("CARGO_MANIFEST_DIR"), filename);
+    let tests = fs::read_to_string(&path).unwrap();
+
+    let candidates = all_moves();
+    let mut errors = 0;
+    for line in tests.replace('\r', "").split('\n') {
+        let fen = line.split(';').next().unwrap();
+        let state = ThreeCheckState::from_fen_with_options(fen, check_limit, variant).unwrap();
+        for _ in 0..games {
+            let mut board = ThreeCheckBoard::from_state(state.clone());
+            for _ in 0..plies {
+                errors += check_position(&mut board, &candidates);
+                let moves = board.gen_moves();
+                if board.game_result() != GameResult::NONE {
+                    break;
+                }
+                board.make_move(moves[rand::random_range(0..moves.len())]);
+            }
+        }
+    }
+    assert_eq!(errors, 0, "{} mismatches in {}", errors, filename);
+}
+
+#[test]
+fn chess960_legality() {
+    check_suite_file("chess960_perft.txt", Some(3), Variant::Standard, 2, 80);
+}
+
+// promotions, en passant and castles that give check are rare in random games
+#[test]
+fn check_legality() {
+    check_suite_file("check_perft.txt", Some(3), Variant::Standard, 8, 4);
+}
+
+#[test]
+fn atomic_legality() {
+    check_suite_file("atomic_perft.txt", None, Variant::Atomic, 2, 80);
+}
+
+#[test]
+fn antichess_legality() {
+    check_suite_file("antichess_perft.txt", None, Variant::Antichess, 2, 80);
+}
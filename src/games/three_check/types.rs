@@ -9,7 +9,7 @@ pub type Bitboard = util::Bitboard<8, 8>;
 
 pub struct SquareParseErr;
 
-fn sq_from_str(s: &str) -> Result<Square, SquareParseErr> {
+pub(super) fn sq_from_str(s: &str) -> Result<Square, SquareParseErr> {
     let mut chrs = s.trim().chars();
     let Some(mut file) = chrs.next() else {
         return Err(SquareParseErr);
@@ -63,7 +63,7 @@ impl fmt::Display for Color {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum PieceType {
     Pawn,
@@ -134,6 +134,21 @@ impl fmt::Display for Piece {
     }
 }
 
+// rule sets layered on top of the check limit, chosen per game
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    KingOfTheHill,
+    Atomic,
+    Antichess,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum MoveKind {
@@ -158,10 +173,14 @@ pub struct Move {
 impl Move {
     pub const NULL: Self = Self { data: 0 };
 
+    // the kind and promotion bits together, normal moves never set the promotion bits so that
+    // value is free for antichess promotions to a king
+    const KING_PROMO_FLAGS: u16 = 1 << 2;
+
     const fn new(from: Square, to: Square, kind: MoveKind, promo: u8) -> Self {
         Self {
             data: from.value()
-                | ((to.value()) << 6)
+                | (to.value() << 6)
                 | ((kind as u16) << 12)
                 | ((promo as u16) << 14),
         }
@@ -180,6 +199,11 @@ impl Move {
     }
 
     pub const fn promo(from: Square, to: Square, promo: PieceType) -> Self {
+        if promo as u8 == PieceType::King as u8 {
+            return Self {
+                data: from.value() | (to.value() << 6) | (Self::KING_PROMO_FLAGS << 12),
+            };
+        }
         Self::new(
             from,
             to,
@@ -188,6 +212,10 @@ impl Move {
         )
     }
 
+    const fn flags(&self) -> u16 {
+        self.data >> 12
+    }
+
     pub const fn from_sq(&self) -> Square {
         Square::from_raw(self.data & 63)
     }
@@ -197,19 +225,34 @@ impl Move {
     }
 
     pub const fn kind(&self) -> MoveKind {
-        MoveKind::from_raw(((self.data >> 12) & 3) as u8)
+        if self.flags() == Self::KING_PROMO_FLAGS {
+            return MoveKind::Promotion;
+        }
+        MoveKind::from_raw((self.flags() & 3) as u8)
     }
 
     pub const fn promo_piece(&self) -> PieceType {
+        if self.flags() == Self::KING_PROMO_FLAGS {
+            return PieceType::King;
+        }
         PieceType::from_raw(((self.data >> 14) + PieceType::Knight as u16) as u8)
     }
+
+    // castles are written king to destination normally, and king takes rook with UCI_Chess960
+    pub fn to_uci(&self, frc: bool) -> String {
+        if self.kind() == MoveKind::Castle && frc {
+            return format!("{}{}", self.from_sq(), self.to_sq());
+        }
+        self.to_string()
+    }
 }
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.kind() == MoveKind::Castle {
-            let offset = if self.to_sq() > self.from_sq() { 2 } else { -2 };
-            return write!(f, "{}{}", self.from_sq(), self.from_sq() + offset);
+            let file = if self.to_sq() > self.from_sq() { 6 } else { 2 };
+            let king_dst = Square::from_rank_file(self.from_sq().rank(), file);
+            return write!(f, "{}{}", self.from_sq(), king_dst);
         }
         write!(f, "{}{}", self.from_sq(), self.to_sq())?;
         if self.kind() == MoveKind::Promotion {
@@ -243,6 +286,8 @@ impl FromStr for Move {
             return Ok(Move::promo(from_sq, to_sq, PieceType::Rook));
         } else if promo.starts_with('q') || promo.starts_with('Q') {
             return Ok(Move::promo(from_sq, to_sq, PieceType::Queen));
+        } else if promo.starts_with('k') || promo.starts_with('K') {
+            return Ok(Move::promo(from_sq, to_sq, PieceType::King));
         }
 
         Ok(Move::normal(from_sq, to_sq))
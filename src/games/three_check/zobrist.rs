@@ -1,8 +1,10 @@
 use super::CastlingRooks;
-use super::{Color, Piece, Square};
+use super::{Color, Piece, Square, ThreeCheckState};
+
+const CHECK_KEYS: usize = ThreeCheckState::MAX_CHECK_LIMIT as usize + 1;
 
 // lol
-const fn xorshift64(mut x: u64) -> u64 {
+pub(super) const fn xorshift64(mut x: u64) -> u64 {
     x ^= x << 13;
     x ^= x >> 7;
     x ^= x << 17;
@@ -13,7 +15,7 @@ struct ZobristKeys {
     piece_squares: [[u64; 12]; 64],
     castling_rights: [u64; 16],
     enpassant: [u64; 8],
-    checks: [[u64; 4]; 2],
+    checks: [[u64; CHECK_KEYS]; 2],
     stm: u64,
 }
 
@@ -22,7 +24,7 @@ const ZOBRIST_KEYS: ZobristKeys = {
         piece_squares: [[0; 12]; 64],
         castling_rights: [0; 16],
         enpassant: [0; 8],
-        checks: [[0; 4]; 2],
+        checks: [[0; CHECK_KEYS]; 2],
         stm: 0,
     };
 
@@ -55,8 +57,8 @@ const ZOBRIST_KEYS: ZobristKeys = {
     }
 
     let mut i = 0;
-    while i < 8 {
-        result.checks[i / 4][i % 4] = rand;
+    while i < 2 * CHECK_KEYS {
+        result.checks[i / CHECK_KEYS][i % CHECK_KEYS] = rand;
         rand = xorshift64(rand);
         i += 1;
     }
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use arrayvec::ArrayVec;
 
 use crate::{
-    games::board::{CopyMakeBoard, CopyMakeWrapper, GameResult},
+    games::board::{BoardIndex, CopyMakeBoard, CopyMakeWrapper, GameResult},
     util::{Square, parse_fen_pieces},
 };
 
@@ -165,3 +165,21 @@ impl fmt::Display for TicTacToeState {
 }
 
 pub type TicTacToeBoard = CopyMakeWrapper<TicTacToeState>;
+
+// each square in base 3, then the side to move
+pub struct TicTacToeIndex;
+
+impl BoardIndex<TicTacToeBoard> for TicTacToeIndex {
+    fn size(&self) -> usize {
+        3usize.pow(9) * 2
+    }
+
+    fn index(&self, board: &TicTacToeBoard) -> Option<usize> {
+        let state = board.curr_state();
+        let mut index = 0;
+        for sq in state.squares.iter().rev() {
+            index = 3 * index + sq.map_or(0, |c| c as usize + 1);
+        }
+        Some(2 * index + state.stm as usize)
+    }
+}
@@ -7,32 +7,163 @@ mod util;
 use std::{io::stdin, str::SplitWhitespace};
 
 use games::{
-    board::Board,
-    connect4::Connect4Board,
-    hexapawn::HexapawnBoard,
-    three_check::{self, Color, ThreeCheckBoard, ThreeCheckState},
-    tictactoe::TicTacToeBoard,
+    ataxx::{AtaxxBoard, AtaxxColor, AtaxxIndex},
+    board::{Board, BoardIndex, GameResult, HashedBoard},
+    connect4::{Connect4Board, Connect4Color, Connect4Index, Connect4Move},
+    hexapawn::{HexapawnBoard, HexapawnIndex},
+    three_check::{self, Color, ThreeCheckBoard, ThreeCheckCopyMakeBoard, ThreeCheckState, Variant},
+    three_check::book::{Book, BookBuilder, BookFormat},
+    three_check::pgn::{self, PgnGame, PgnReader},
+    tictactoe::{TicTacToeBoard, TicTacToeIndex},
 };
 use search::{
     ab_solver::ABSolver,
-    c4_solver::{C4Benchmark, run_benchmark},
+    ataxx::AtaxxSearch,
+    c4_book::{C4Book, generate_book},
+    c4_solver::{C4Benchmark, Connect4Solver, run_benchmark},
+    connect4::Connect4Search,
+    retrograde::{RetrogradeSearch, RetrogradeTable},
     search::{Search, SearchLimits},
     three_check::ThreeCheckSearch,
+    three_check_tb::{Material, ThreeCheckTablebase},
 };
 use util::Square;
 
 pub fn parse_move(board: &ThreeCheckBoard, str: &str) -> three_check::Move {
     let legal_moves = board.gen_moves();
+    let frc = board.curr_state().is_frc();
     for mv in legal_moves {
-        if mv.to_string() == str {
+        if mv.to_uci(frc) == str {
             return mv;
         }
     }
     panic!("WTF {}", str);
 }
 
-fn parse_startpos(curr_board: &mut ThreeCheckBoard, toks: &mut SplitWhitespace<'_>) {
-    *curr_board = ThreeCheckBoard::startpos();
+struct ThreeCheckOptions {
+    check_limit: u8,
+    // None until UCI_3Check is sent, then checks are only on by default in standard chess
+    checks_enabled: Option<bool>,
+    chess960: bool,
+    variant: Variant,
+    own_book: bool,
+    book: Option<Book>,
+}
+
+impl ThreeCheckOptions {
+    fn new() -> Self {
+        Self {
+            check_limit: 3,
+            checks_enabled: None,
+            chess960: false,
+            variant: Variant::Standard,
+            own_book: false,
+            book: None,
+        }
+    }
+
+    fn book_move(&self, board: &ThreeCheckBoard) -> Option<three_check::Move> {
+        if !self.own_book {
+            return None;
+        }
+        self.book.as_ref()?.probe(board.curr_state())
+    }
+
+    fn check_limit(&self) -> Option<u8> {
+        if self.checks_enabled.unwrap_or(self.variant == Variant::Standard) {
+            Some(self.check_limit)
+        } else {
+            None
+        }
+    }
+
+    fn make_state(&self, fen: Option<&str>) -> Option<ThreeCheckState> {
+        let mut state = match fen {
+            Some(fen) => {
+                ThreeCheckState::from_fen_with_options(fen, self.check_limit(), self.variant)?
+            }
+            None => ThreeCheckState::startpos_with_options(self.check_limit(), self.variant),
+        };
+        if self.chess960 {
+            state.enable_frc();
+        }
+        Some(state)
+    }
+
+    // variants are separate check options, turning one off only resets it if it is active
+    fn set_variant(&mut self, variant: Variant, value: &str) -> bool {
+        match value {
+            "true" => self.variant = variant,
+            "false" if self.variant == variant => self.variant = Variant::Standard,
+            "false" => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> bool {
+        match name {
+            "UCI_3Check" => match value {
+                "true" => self.checks_enabled = Some(true),
+                "false" => self.checks_enabled = Some(false),
+                _ => return false,
+            },
+            "UCI_Chess960" => match value {
+                "true" => self.chess960 = true,
+                "false" => self.chess960 = false,
+                _ => return false,
+            },
+            "UCI_KingOfTheHill" => return self.set_variant(Variant::KingOfTheHill, value),
+            "UCI_Atomic" => return self.set_variant(Variant::Atomic, value),
+            "UCI_Antichess" => return self.set_variant(Variant::Antichess, value),
+            "OwnBook" => match value {
+                "true" => self.own_book = true,
+                "false" => self.own_book = false,
+                _ => return false,
+            },
+            "BookFile" => match value {
+                "" | "<empty>" => self.book = None,
+                _ => match Book::from_file(value) {
+                    Some(book) => self.book = Some(book),
+                    None => return false,
+                },
+            },
+            "CheckLimit" => match value.parse::<u8>() {
+                Ok(n) if (1..=ThreeCheckState::MAX_CHECK_LIMIT).contains(&n) => self.check_limit = n,
+                _ => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+// setoption name <name> value <value>, names and values may contain spaces
+fn parse_setoption(toks: &mut SplitWhitespace<'_>) -> Option<(String, String)> {
+    if toks.next() != Some("name") {
+        return None;
+    }
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    let mut in_value = false;
+    for tok in toks {
+        if tok == "value" && !in_value {
+            in_value = true;
+        } else if in_value {
+            value.push(tok);
+        } else {
+            name.push(tok);
+        }
+    }
+    Some((name.join(" "), value.join(" ")))
+}
+
+fn parse_startpos(
+    curr_board: &mut ThreeCheckBoard,
+    toks: &mut SplitWhitespace<'_>,
+    options: &ThreeCheckOptions,
+) {
+    *curr_board = ThreeCheckBoard::from_state(options.make_state(None).unwrap());
     if toks.next() == Some("moves") {
         loop {
             let mv_str = toks.next();
@@ -45,7 +176,11 @@ fn parse_startpos(curr_board: &mut ThreeCheckBoard, toks: &mut SplitWhitespace<'
     }
 }
 
-fn parse_fen(curr_board: &mut ThreeCheckBoard, toks: &mut SplitWhitespace<'_>) {
+fn parse_fen(
+    curr_board: &mut ThreeCheckBoard,
+    toks: &mut SplitWhitespace<'_>,
+    options: &ThreeCheckOptions,
+) {
     let mut fen = toks.next().unwrap().to_string();
     let mut tok = toks.next();
     loop {
@@ -56,7 +191,7 @@ fn parse_fen(curr_board: &mut ThreeCheckBoard, toks: &mut SplitWhitespace<'_>) {
         fen += tok.unwrap();
         tok = toks.next();
     }
-    *curr_board = ThreeCheckBoard::from_fen(fen.as_str()).unwrap();
+    *curr_board = ThreeCheckBoard::from_state(options.make_state(Some(fen.as_str())).unwrap());
     if tok == Some("moves") {
         loop {
             let mv_str = toks.next();
@@ -77,6 +212,7 @@ fn select_random_move(board: &ThreeCheckBoard) -> three_check::Move {
 fn run_three_check() {
     let mut curr_board = ThreeCheckBoard::startpos();
     let mut search = ThreeCheckSearch::new();
+    let mut options = ThreeCheckOptions::new();
     loop {
         let mut command = String::new();
         stdin().read_line(&mut command).expect("Bad input");
@@ -87,8 +223,34 @@ fn run_three_check() {
                 println!("id author mcthouacbb");
                 println!("option name Hash type spin default 1 min 1 max 1");
                 println!("option name UCI_3Check type check default true");
+                println!("option name UCI_Chess960 type check default false");
+                println!("option name UCI_KingOfTheHill type check default false");
+                println!("option name UCI_Atomic type check default false");
+                println!("option name UCI_Antichess type check default false");
+                println!(
+                    "option name CheckLimit type spin default 3 min 1 max {}",
+                    ThreeCheckState::MAX_CHECK_LIMIT
+                );
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!("option name TBPath type string default <empty>");
                 println!("uciok");
             }
+            Some("setoption") => match parse_setoption(&mut toks) {
+                // the tables belong to the search rather than the position options
+                Some((name, value)) if name == "TBPath" => {
+                    let tablebase = match value.as_str() {
+                        "" | "<empty>" => ThreeCheckTablebase::new(),
+                        dir => ThreeCheckTablebase::load_dir(dir),
+                    };
+                    println!("info string loaded {} tables", tablebase.len());
+                    search.set_tablebase(tablebase);
+                }
+                Some((name, value)) if options.set(&name, &value) => {}
+                _ => {
+                    println!("info string invalid option");
+                }
+            },
             Some("isready") => {
                 println!("readyok");
             }
@@ -97,16 +259,33 @@ fn run_three_check() {
             }
             Some("position") => match toks.next() {
                 Some("startpos") => {
-                    parse_startpos(&mut curr_board, &mut toks);
+                    parse_startpos(&mut curr_board, &mut toks, &options);
                 }
                 Some("fen") => {
-                    parse_fen(&mut curr_board, &mut toks);
+                    parse_fen(&mut curr_board, &mut toks, &options);
                 }
                 _ => {
                     println!("info string invalid command");
                 }
             },
             Some("go") => {
+                if toks.clone().next() == Some("perft") {
+                    toks.next();
+                    match toks.next().and_then(|depth| depth.parse().ok()) {
+                        Some(depth) => {
+                            let frc = curr_board.curr_state().is_frc();
+                            perft::perft_divide(&mut curr_board, depth, |mv| mv.to_uci(frc));
+                        }
+                        None => {
+                            println!("info string invalid command");
+                        }
+                    }
+                    continue;
+                }
+                if let Some(mv) = options.book_move(&curr_board) {
+                    println!("bestmove {}", mv.to_uci(curr_board.curr_state().is_frc()));
+                    continue;
+                }
                 let mut limits = SearchLimits::default();
                 loop {
                     match toks.next() {
@@ -130,12 +309,248 @@ fn run_three_check() {
                 }
                 let results = search.search(&curr_board, limits);
                 // println!("bestmove {}", select_random_move(&curr_board));
-                println!("bestmove {}", results.best_move);
+                println!(
+                    "bestmove {}",
+                    results.best_move.to_uci(curr_board.curr_state().is_frc())
+                );
             }
             Some("aaa") => {
                 for mv in curr_board.gen_moves() {
-                    println!("{}", mv);
+                    println!("{}", mv.to_uci(curr_board.curr_state().is_frc()));
+                }
+            }
+            Some("d") => {
+                println!("{}", &mut curr_board);
+            }
+            Some("quit") => {
+                return;
+            }
+            _ => {
+                println!("info string invalid command");
+            }
+        }
+    }
+}
+
+// a share of the clock plus most of the increment, keeping a quarter of the clock in reserve
+fn time_for_move(time: u64, inc: u64) -> u64 {
+    (time / 30 + inc * 3 / 4).min(time * 3 / 4)
+}
+
+fn parse_ataxx_move(board: &AtaxxBoard, str: &str) -> games::ataxx::AtaxxMove {
+    let legal_moves = board.gen_moves();
+    for mv in legal_moves {
+        if mv.to_string() == str {
+            return mv;
+        }
+    }
+    panic!("WTF {}", str);
+}
+
+fn parse_ataxx_position(curr_board: &mut AtaxxBoard, toks: &mut SplitWhitespace<'_>) {
+    let mut tok = toks.next();
+    match tok {
+        Some("startpos") => {
+            *curr_board = AtaxxBoard::startpos();
+            tok = toks.next();
+        }
+        Some("fen") => {
+            let mut fen = String::new();
+            tok = toks.next();
+            while tok.is_some() && tok != Some("moves") {
+                fen += tok.unwrap();
+                fen += " ";
+                tok = toks.next();
+            }
+            *curr_board = AtaxxBoard::from_fen(fen.as_str()).unwrap();
+        }
+        _ => {
+            println!("info string invalid command");
+            return;
+        }
+    }
+    if tok == Some("moves") {
+        for str in toks {
+            curr_board.make_move(parse_ataxx_move(curr_board, str));
+        }
+    }
+}
+
+fn run_ataxx() {
+    let mut curr_board = AtaxxBoard::startpos();
+    let mut search = AtaxxSearch::new();
+    loop {
+        let mut command = String::new();
+        stdin().read_line(&mut command).expect("Bad input");
+        let mut toks = command.split_whitespace();
+        match toks.next() {
+            Some("uai") => {
+                println!("id name calamity");
+                println!("id author mcthouacbb");
+                println!("uaiok");
+            }
+            Some("isready") => {
+                println!("readyok");
+            }
+            Some("uainewgame") => {
+                search.clear();
+            }
+            Some("position") => {
+                parse_ataxx_position(&mut curr_board, &mut toks);
+            }
+            Some("go") => {
+                let mut limits = SearchLimits::default();
+                let stm = curr_board.curr_state().stm();
+                let mut time = [None; 2];
+                let mut inc = [0; 2];
+                let parse_ms =
+                    |tok: Option<&str>| tok.unwrap().parse::<i64>().unwrap().max(0) as u64;
+                loop {
+                    match toks.next() {
+                        Some("btime") => {
+                            time[AtaxxColor::Black as usize] = Some(parse_ms(toks.next()))
+                        }
+                        Some("wtime") => {
+                            time[AtaxxColor::White as usize] = Some(parse_ms(toks.next()))
+                        }
+                        Some("binc") => inc[AtaxxColor::Black as usize] = parse_ms(toks.next()),
+                        Some("winc") => inc[AtaxxColor::White as usize] = parse_ms(toks.next()),
+                        Some("movetime") => {
+                            limits.max_time = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some("depth") => {
+                            limits.max_depth = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some("nodes") => {
+                            limits.max_nodes = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                if limits.max_time.is_none()
+                    && let Some(time) = time[stm as usize]
+                {
+                    limits.max_time = Some(time_for_move(time, inc[stm as usize]));
+                }
+                let results = search.search(&curr_board, limits);
+                println!("bestmove {}", results.best_move);
+            }
+            Some("d") => {
+                println!("{}", &mut curr_board);
+            }
+            Some("quit") => {
+                return;
+            }
+            _ => {
+                println!("info string invalid command");
+            }
+        }
+    }
+}
+
+fn parse_c4_move(board: &Connect4Board, str: &str) -> Option<Connect4Move> {
+    board.gen_moves().into_iter().find(|mv| mv.to_string() == str)
+}
+
+// position <startpos|fen <fen>> [moves <column>...], with columns counting from 1
+fn parse_c4_position(curr_board: &mut Connect4Board, toks: &mut SplitWhitespace<'_>) {
+    let mut tok = toks.next();
+    match tok {
+        Some("startpos") => {
+            *curr_board = Connect4Board::startpos();
+            tok = toks.next();
+        }
+        Some("fen") => {
+            let mut fen = String::new();
+            tok = toks.next();
+            while tok.is_some() && tok != Some("moves") {
+                fen += tok.unwrap();
+                fen += " ";
+                tok = toks.next();
+            }
+            let Some(board) = Connect4Board::from_fen(fen.as_str()) else {
+                println!("info string invalid fen");
+                return;
+            };
+            *curr_board = board;
+        }
+        _ => {
+            println!("info string invalid command");
+            return;
+        }
+    }
+    if tok == Some("moves") {
+        for str in toks {
+            let Some(mv) = parse_c4_move(curr_board, str) else {
+                println!("info string illegal move {}", str);
+                return;
+            };
+            curr_board.make_move(mv);
+        }
+    }
+}
+
+// plays 7x6 connect 4 under time limits, the clock options are rtime and ytime for red and
+// yellow, with rinc and yinc as their increments
+fn run_connect4() {
+    let mut curr_board = Connect4Board::startpos();
+    let mut search = Connect4Search::new();
+    loop {
+        let mut command = String::new();
+        stdin().read_line(&mut command).expect("Bad input");
+        let mut toks = command.split_whitespace();
+        match toks.next() {
+            Some("isready") => {
+                println!("readyok");
+            }
+            Some("newgame") => {
+                search.clear();
+            }
+            Some("position") => {
+                parse_c4_position(&mut curr_board, &mut toks);
+            }
+            Some("go") => {
+                if curr_board.game_result() != GameResult::NONE {
+                    println!("info string game over");
+                    continue;
                 }
+                let mut limits = SearchLimits::default();
+                let stm = curr_board.curr_state().stm();
+                let mut time = [None; 2];
+                let mut inc = [0; 2];
+                let parse_ms =
+                    |tok: Option<&str>| tok.unwrap().parse::<i64>().unwrap().max(0) as u64;
+                loop {
+                    match toks.next() {
+                        Some("rtime") => {
+                            time[Connect4Color::Red as usize] = Some(parse_ms(toks.next()))
+                        }
+                        Some("ytime") => {
+                            time[Connect4Color::Yellow as usize] = Some(parse_ms(toks.next()))
+                        }
+                        Some("rinc") => inc[Connect4Color::Red as usize] = parse_ms(toks.next()),
+                        Some("yinc") => inc[Connect4Color::Yellow as usize] = parse_ms(toks.next()),
+                        Some("movetime") => {
+                            limits.max_time = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some("depth") => {
+                            limits.max_depth = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some("nodes") => {
+                            limits.max_nodes = Some(toks.next().unwrap().parse().unwrap());
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                if limits.max_time.is_none()
+                    && let Some(time) = time[stm as usize]
+                {
+                    limits.max_time = Some(time_for_move(time, inc[stm as usize]));
+                }
+                let results = search.search(&curr_board, limits);
+                println!("bestmove {}", results.best_move);
             }
             Some("d") => {
                 println!("{}", &mut curr_board);
@@ -150,9 +565,706 @@ fn run_three_check() {
     }
 }
 
+fn perft_report<B: HashedBoard + Sync>(board: B, depth: u32, threads: usize, hash_mb: usize)
+where
+    B::Move: Sync,
+{
+    if hash_mb > 0 {
+        let table = perft::PerftTable::new(hash_mb);
+        perft::perft_report(&board, depth, |board, depth| {
+            perft::hashed_perft(board, depth, threads, &table)
+        });
+    } else {
+        perft::perft_report(&board, depth, |board, depth| {
+            perft::fast_perft(board, depth, threads)
+        });
+    }
+}
+
+// runs body with $board as the connect 4 board of the given (columns, rows) size. every size from
+// 4x4 to 9x7 where the board and a sentinel row fit in 64 bits is supported
+macro_rules! with_c4_size {
+    ($size:expr, $board:ident => $body:block) => {
+        with_c4_size!(@sizes $size, $board, $body,
+            (4, 4) (5, 4) (6, 4) (7, 4) (8, 4) (9, 4)
+            (4, 5) (5, 5) (6, 5) (7, 5) (8, 5) (9, 5)
+            (4, 6) (5, 6) (6, 6) (7, 6) (8, 6) (9, 6)
+            (4, 7) (5, 7) (6, 7) (7, 7) (8, 7))
+    };
+    (@sizes $size:expr, $board:ident, $body:block, $(($columns:literal, $rows:literal))*) => {
+        match $size {
+            $(($columns, $rows) => {
+                type $board = Connect4Board<$columns, $rows>;
+                $body
+            })*
+            (columns, rows) => println!("unsupported connect 4 board size {}x{}", columns, rows),
+        }
+    };
+}
+
+// <columns>x<rows> like 7x6
+fn parse_c4_size(size: &str) -> Option<(u8, u8)> {
+    let (columns, rows) = size.split_once('x')?;
+    Some((columns.parse().ok()?, rows.parse().ok()?))
+}
+
+// perft <three_check|c4|c4:<columns>x<rows>|ataxx> <depth> [threads] [hash mb]
+fn run_perft_report() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let depth = args.get(1).and_then(|arg| arg.parse().ok()).unwrap_or(5);
+    let threads = args
+        .get(2)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(perft::default_threads);
+    let hash_mb = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(0);
+    match args.first().map(String::as_str) {
+        Some("c4") => perft_report(Connect4Board::<7, 6>::startpos(), depth, threads, hash_mb),
+        Some(game) if game.starts_with("c4:") => {
+            let Some(size) = parse_c4_size(&game[3..]) else {
+                println!("invalid connect 4 board size {}", &game[3..]);
+                return;
+            };
+            with_c4_size!(size, B => {
+                perft_report(B::startpos(), depth, threads, hash_mb)
+            })
+        }
+        Some("ataxx") => perft_report(AtaxxBoard::startpos(), depth, threads, hash_mb),
+        _ => perft_report(ThreeCheckBoard::startpos(), depth, threads, hash_mb),
+    }
+}
+
+// the variant a perft suite is for, and the suite it runs when no file is given
+fn parse_suite_variant(name: &str) -> Option<(Variant, &'static str)> {
+    match name {
+        "three_check" => Some((Variant::Standard, "res/three_check_perft.txt")),
+        "atomic" => Some((Variant::Atomic, "res/atomic_perft.txt")),
+        "antichess" => Some((Variant::Antichess, "res/antichess_perft.txt")),
+        _ => None,
+    }
+}
+
+// fens with a check count field are three check, the others have no check limit
+fn suite_board_from_fen(fen: &str, variant: Variant) -> Option<ThreeCheckBoard> {
+    let check_limit = (fen.split_whitespace().count() == 7).then_some(3);
+    let state = ThreeCheckState::from_fen_with_options(fen, check_limit, variant)?;
+    Some(ThreeCheckBoard::from_state(state))
+}
+
+fn chess_move_str(board: &ThreeCheckBoard, mv: three_check::Move) -> String {
+    mv.to_uci(board.curr_state().is_frc())
+}
+
+fn display_move_str<B: Board>(_: &B, mv: B::Move) -> String
+where
+    B::Move: std::fmt::Display,
+{
+    mv.to_string()
+}
+
+// args are everything after the game: [suite file] [max depth] [max seconds] [divide reference file]
+fn perft_suite_for<B: Board + Sync>(
+    args: &[String],
+    default_file: &str,
+    from_fen: impl Fn(&str) -> Option<B>,
+    move_str: fn(&B, B::Move) -> String,
+) where
+    B::Move: Sync,
+{
+    let filename = args.first().map_or(default_file, String::as_str);
+    let mut options = perft::PerftSuiteOptions::<B> {
+        max_depth: args.get(1).and_then(|arg| arg.parse().ok()),
+        max_time: args
+            .get(2)
+            .and_then(|arg| arg.parse().ok())
+            .map(std::time::Duration::from_secs),
+        move_str,
+        ..Default::default()
+    };
+    if let Some(reference) = args.get(3) {
+        match perft::DivideReference::from_file(reference) {
+            Ok(reference) => options.reference = Some(reference),
+            Err(err) => {
+                println!("{}: {}", reference, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    let tests = match std::fs::read_to_string(filename) {
+        Ok(tests) => tests,
+        Err(err) => {
+            println!("{}: {}", filename, err);
+            std::process::exit(1);
+        }
+    };
+    let report = perft::perft_suite_report(&tests, from_fen, &options);
+    println!("{}", report);
+    if !report.all_passed() {
+        std::process::exit(1);
+    }
+}
+
+// perftsuite <c4|ataxx|three_check|atomic|antichess> [suite file] [max depth] [max seconds]
+// [divide reference file]
+fn run_perft_suite() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let game = args.first().map_or("three_check", String::as_str);
+    let rest = args.get(1..).unwrap_or_default();
+    match game {
+        "c4" => perft_suite_for(
+            rest,
+            "res/c4_perft.txt",
+            Connect4Board::<7, 6>::from_fen,
+            display_move_str,
+        ),
+        "ataxx" => perft_suite_for(
+            rest,
+            "res/ataxx_perft.txt",
+            AtaxxBoard::from_fen,
+            display_move_str,
+        ),
+        _ => match parse_suite_variant(game) {
+            Some((variant, default_file)) => perft_suite_for(
+                rest,
+                default_file,
+                |fen| suite_board_from_fen(fen, variant),
+                chess_move_str,
+            ),
+            None => println!(
+                "usage: perftsuite <c4|ataxx|three_check|atomic|antichess> [suite file] [max depth] [max seconds] [divide reference file]"
+            ),
+        },
+    }
+}
+
+fn divide_reference_for<B: Board + Sync>(
+    fen: &str,
+    depth: u32,
+    levels: u32,
+    board: Option<B>,
+    move_str: fn(&B, B::Move) -> String,
+) where
+    B::Move: Sync,
+{
+    let Some(mut board) = board else {
+        println!("invalid fen {}", fen);
+        return;
+    };
+    for line in perft::divide_reference_lines(&mut board, fen, depth, levels, move_str) {
+        println!("{}", line);
+    }
+}
+
+// perftref <c4|ataxx|three_check|atomic|antichess> <depth> <levels> <fen>, prints divide
+// reference lines for a suite position
+fn run_divide_reference() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    if args.len() < 4 {
+        println!(
+            "usage: perftref <c4|ataxx|three_check|atomic|antichess> <depth> <levels> <fen>"
+        );
+        return;
+    }
+    let depth = args[1].parse().unwrap_or(4);
+    let levels = args[2].parse().unwrap_or(2);
+    let fen = args[3..].join(" ");
+    match args[0].as_str() {
+        "c4" => divide_reference_for(
+            &fen,
+            depth,
+            levels,
+            Connect4Board::<7, 6>::from_fen(&fen),
+            display_move_str,
+        ),
+        "ataxx" => divide_reference_for(
+            &fen,
+            depth,
+            levels,
+            AtaxxBoard::from_fen(&fen),
+            display_move_str,
+        ),
+        game => match parse_suite_variant(game) {
+            Some((variant, _)) => divide_reference_for(
+                &fen,
+                depth,
+                levels,
+                suite_board_from_fen(&fen, variant),
+                chess_move_str,
+            ),
+            None => println!("unknown game {}", game),
+        },
+    }
+}
+
+// makebook <pgn file|selfplay games> <output> [max ply] [polyglot]
+// own format books take the variant and check limit from the games, polyglot books ignore checks
+fn run_make_book() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let selfplay = args.first().is_some_and(|arg| arg == "selfplay");
+    if args.len() < 2 || (selfplay && args.len() < 3) {
+        println!("usage: makebook <pgn file|selfplay games> <output> [max ply] [polyglot]");
+        return;
+    }
+    let (output, max_ply) = if selfplay {
+        (&args[2], args.get(3))
+    } else {
+        (&args[1], args.get(2))
+    };
+    let max_ply = max_ply.and_then(|arg| arg.parse().ok()).unwrap_or(16);
+    let polyglot = args.iter().any(|arg| arg == "polyglot");
+    let make_builder = |state: &ThreeCheckState| {
+        let format = if polyglot {
+            BookFormat::Polyglot
+        } else {
+            BookFormat::Variant {
+                variant: state.variant(),
+                check_limit: state.check_limit(),
+            }
+        };
+        BookBuilder::new(format, max_ply, 1)
+    };
+
+    let mut games = 0;
+    let builder = if selfplay {
+        let count = args[1].parse().unwrap_or(100);
+        let start = ThreeCheckState::startpos();
+        let mut builder = make_builder(&start);
+        let mut search = ThreeCheckSearch::new();
+        for _ in 0..count {
+            let (opening, moves, result) = play_book_game(&mut search, &start);
+            builder.add_game(&opening, &moves, result);
+            games += 1;
+        }
+        builder
+    } else {
+        let Ok(file) = std::fs::File::open(&args[0]) else {
+            println!("could not open {}", args[0]);
+            return;
+        };
+        let mut builder = None;
+        for game in PgnReader::new(std::io::BufReader::new(file)) {
+            match game {
+                Ok(game) => {
+                    builder
+                        .get_or_insert_with(|| make_builder(&game.start))
+                        .add_pgn_game(&game);
+                    games += 1;
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+        builder.unwrap_or_else(|| make_builder(&ThreeCheckState::startpos()))
+    };
+
+    let book = builder.build();
+    match book.save(output) {
+        Ok(()) => println!("{} games, {} entries written to {}", games, book.len(), output),
+        Err(err) => println!("{}: {}", output, err),
+    }
+}
+
+// pgnexport <pgn file|selfplay games> <output>
+// rewrites games in export format, selfplay games are played the same way as for makebook
+fn run_pgn_export() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    if args.len() < 2 || (args[0] == "selfplay" && args.len() < 3) {
+        println!("usage: pgnexport <pgn file|selfplay games> <output>");
+        return;
+    }
+    let mut pgn = String::new();
+    let mut games = 0;
+    let output = if args[0] == "selfplay" {
+        let count = args[1].parse().unwrap_or(10);
+        let mut search = ThreeCheckSearch::new();
+        for _ in 0..count {
+            let (opening, moves, result) =
+                play_book_game(&mut search, &ThreeCheckState::startpos());
+            let mut game = PgnGame::from_moves(opening, &moves, result);
+            game.set_tag("Event", "selfplay");
+            pgn += game.to_pgn().as_str();
+            games += 1;
+        }
+        &args[2]
+    } else {
+        let Ok(file) = std::fs::File::open(&args[0]) else {
+            println!("could not open {}", args[0]);
+            return;
+        };
+        for game in PgnReader::new(std::io::BufReader::new(file)) {
+            match game {
+                Ok(game) => {
+                    pgn += game.to_pgn().as_str();
+                    games += 1;
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+        &args[1]
+    };
+    match std::fs::write(output, pgn) {
+        Ok(()) => println!("{} games written to {}", games, output),
+        Err(err) => println!("{}: {}", output, err),
+    }
+}
+
+// pgncheck [pgn file]
+// writes every game and reads it back, the text and the final position have to come out the same
+fn run_pgn_check() {
+    let filename = std::env::args()
+        .nth(2)
+        .unwrap_or("res/sample.pgn".to_string());
+    let Ok(file) = std::fs::File::open(&filename) else {
+        println!("could not open {}", filename);
+        return;
+    };
+    let mut games = 0;
+    let mut errors = 0;
+    for game in PgnReader::new(std::io::BufReader::new(file)) {
+        games += 1;
+        let game = match game {
+            Ok(game) => game,
+            Err(err) => {
+                println!("{}", err);
+                errors += 1;
+                continue;
+            }
+        };
+        let text = game.to_pgn();
+        let reread = match pgn::parse_game(&text, 1) {
+            Ok(reread) => reread,
+            Err(err) => {
+                println!(
+                    "{} does not read back: {}\n{}",
+                    game.tag("Event").unwrap_or("?"),
+                    err,
+                    text
+                );
+                errors += 1;
+                continue;
+            }
+        };
+        let (board, reread_board) = (game.board(), reread.board());
+        let (state, reread_state) = (board.curr_state(), reread_board.curr_state());
+        if reread.to_pgn() != text
+            || reread_state.to_fen() != state.to_fen()
+            || reread_state.variant() != state.variant()
+            || reread_state.is_frc() != state.is_frc()
+        {
+            println!(
+                "{} changed:\n{}\n{}",
+                game.tag("Event").unwrap_or("?"),
+                text,
+                reread.to_pgn()
+            );
+            errors += 1;
+        }
+    }
+    println!("{}: {} games, {} mismatches", filename, games, errors);
+}
+
+// a few random moves so games differ, then a shallow search for both sides. only the searched
+// moves go into the book, starting from the position after the random ones
+fn play_book_game(
+    search: &mut ThreeCheckSearch,
+    start: &ThreeCheckState,
+) -> (ThreeCheckState, Vec<three_check::Move>, &'static str) {
+    let mut board = ThreeCheckBoard::from_state(start.clone());
+    for _ in 0..4 {
+        let mv = select_random_move(&board);
+        board.make_move(mv);
+    }
+    let opening = board.curr_state().clone();
+    let mut moves = Vec::new();
+    let limits = SearchLimits {
+        max_depth: Some(6),
+        ..Default::default()
+    };
+    while board.game_result() == GameResult::NONE && !board.is_drawn(false) && moves.len() < 200 {
+        let mv = search.search(&board, limits).best_move;
+        board.make_move(mv);
+        moves.push(mv);
+    }
+    let white_to_move = board.curr_state().stm() == Color::White;
+    let result = match board.game_result() {
+        GameResult::WIN if white_to_move => "1-0",
+        GameResult::LOSS if white_to_move => "0-1",
+        GameResult::WIN => "0-1",
+        GameResult::LOSS => "1-0",
+        _ => "1/2-1/2",
+    };
+    (opening, moves, result)
+}
+
+// compares every position reachable from root with ABSolver, up to a limit
+fn verify_retrograde<B: Board, I: BoardIndex<B>>(
+    search: &RetrogradeSearch<B, I>,
+    root: &B,
+    limit: usize,
+) -> (usize, usize) {
+    let mut seen = std::collections::HashSet::new();
+    let mut stack = vec![root.clone()];
+    let mut solver = ABSolver::<B>::new();
+    let mut errors = 0;
+    while let Some(mut board) = stack.pop() {
+        if seen.len() >= limit || !seen.insert(search.index().index(&board).unwrap()) {
+            continue;
+        }
+        if board.game_result() != GameResult::NONE {
+            continue;
+        }
+        let expected = solver.search(&board, SearchLimits::default()).score;
+        if search.score(&board) != Some(expected) {
+            errors += 1;
+        }
+        for mv in board.gen_moves() {
+            if board.make_move(mv) {
+                stack.push(board.clone());
+                board.unmake_move();
+            }
+        }
+    }
+    (seen.len(), errors)
+}
+
+fn retrograde_report<B: Board, I: BoardIndex<B>>(name: &str, index: I, root: B, verify: bool) {
+    let start = std::time::Instant::now();
+    let table = RetrogradeTable::generate(&index, std::slice::from_ref(&root));
+    let time = start.elapsed();
+    let stats = table.stats();
+    let filename = format!("{}.rtb", name);
+    let loaded = table
+        .save(&filename)
+        .ok()
+        .and_then(|_| RetrogradeTable::load(&filename));
+    let Some(table) = loaded else {
+        println!("{}: could not write {}", name, filename);
+        return;
+    };
+    let search = RetrogradeSearch::new(table, index);
+    println!(
+        "{}: {} positions, {} wins {} draws {} losses, longest {} plies, root {:?} in {} ms",
+        name,
+        stats.positions,
+        stats.wins,
+        stats.draws,
+        stats.losses,
+        stats.max_distance,
+        search.score(&root),
+        time.as_millis()
+    );
+    if verify {
+        let (positions, errors) = verify_retrograde(&search, &root, 100000);
+        println!("{}: {} positions checked against ABSolver, {} mismatches", name, positions, errors);
+    }
+}
+
+// generates and checks tables for the small games, writing them to the working directory
+fn run_retrograde() {
+    retrograde_report("tictactoe", TicTacToeIndex, TicTacToeBoard::startpos(), true);
+    retrograde_report("hexapawn", HexapawnIndex, HexapawnBoard::startpos(), true);
+    let ataxx = AtaxxBoard::from_fen("-------/-------/-------/-------/-------/x3o--/o3x-- x 0 1")
+        .unwrap();
+    let index = AtaxxIndex::new(ataxx.curr_state().blockers());
+    retrograde_report("ataxx5x2", index, ataxx, true);
+    retrograde_report("c4_4x4", Connect4Index, Connect4Board::<4, 4>::startpos(), true);
+    retrograde_report("c4_5x4", Connect4Index, Connect4Board::<5, 4>::startpos(), true);
+}
+
+// tbgen <dir> [max pieces] [check limit], a check limit of 0 builds tables for plain chess
+fn run_tablebase_gen() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let Some(dir) = args.first() else {
+        println!("usage: tbgen <dir> [max pieces] [check limit]");
+        return;
+    };
+    let max_pieces = args.get(1).and_then(|arg| arg.parse().ok()).unwrap_or(3);
+    let check_limit = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(3);
+    let mut tablebase = ThreeCheckTablebase::new();
+    for material in Material::all(max_pieces) {
+        let start = std::time::Instant::now();
+        tablebase.generate(&material, check_limit);
+        let stats = tablebase.stats(&material, check_limit).unwrap();
+        println!(
+            "{}: {} positions, {} wins {} draws {} losses, longest {} plies in {} ms",
+            material,
+            stats.positions,
+            stats.wins,
+            stats.draws,
+            stats.losses,
+            stats.max_distance,
+            start.elapsed().as_millis()
+        );
+    }
+    if let Err(err) = tablebase.save_dir(dir) {
+        println!("could not write tables to {}: {}", dir, err);
+        return;
+    }
+    println!("{} tables written to {}", tablebase.len(), dir);
+}
+
+// c4book <max ply> <output> [fen], solving every position from the fen or the empty board
+fn run_c4_book_gen() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    if args.len() < 2 {
+        println!("usage: c4book <max ply> <output> [fen]");
+        return;
+    }
+    let Ok(max_ply) = args[0].parse() else {
+        println!("invalid ply {}", args[0]);
+        return;
+    };
+    let root: Connect4Board = if args.len() > 2 {
+        let fen = args[2..].join(" ");
+        let Some(board) = Connect4Board::from_fen(&fen) else {
+            println!("invalid fen {}", fen);
+            return;
+        };
+        board
+    } else {
+        Connect4Board::startpos()
+    };
+    let book = generate_book(&root, max_ply, |layer| {
+        println!(
+            "ply {}: {} positions, {} nodes in {} ms",
+            layer.ply,
+            layer.positions,
+            layer.nodes,
+            layer.time.as_millis()
+        );
+    });
+    match book.save(&args[1]) {
+        Ok(()) => println!("{} positions written to {}", book.len(), args[1]),
+        Err(err) => println!("could not write {}: {}", args[1], err),
+    }
+}
+
+// c4solve <columns>x<rows> [weak] [fen], solving the fen or the empty board of that size
+fn run_c4_solve() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let Some(size) = args.first().and_then(|arg| parse_c4_size(arg)) else {
+        println!("usage: c4solve <columns>x<rows> [weak] [fen]");
+        return;
+    };
+    let weak = args.get(1).is_some_and(|arg| arg == "weak");
+    let fen = args[1 + weak as usize..].join(" ");
+    with_c4_size!(size, B => {
+        let board = if fen.is_empty() {
+            B::startpos()
+        } else {
+            let Some(board) = B::from_fen(&fen) else {
+                println!("invalid fen {}", fen);
+                return;
+            };
+            board
+        };
+        println!("{}", board);
+        let mut solver = Connect4Solver::new();
+        solver.set_weak(weak);
+        let result = solver.search(&board, SearchLimits::default());
+        for iteration in solver.iterations() {
+            println!(
+                "window ({}, {}) score {} nodes {} time {}",
+                iteration.alpha,
+                iteration.beta,
+                iteration.score,
+                iteration.nodes,
+                iteration.time.as_secs_f64()
+            );
+        }
+        println!(
+            "score {} best move column {} nodes {} time {} nps {}",
+            result.score,
+            result.best_move,
+            result.nodes,
+            result.time.as_secs_f64(),
+            result.nodes as f64 / result.time.as_secs_f64()
+        );
+    })
+}
+
+// c4bench <end_easy|mid_easy|mid_medium|begin_easy|begin_medium|begin_hard|all> [positions]
+// [weak] [book], where weak only solves for win, draw or loss
+fn run_c4_bench() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let benchmarks = match args.first().map(|arg| arg.as_str()) {
+        Some("end_easy") => vec![C4Benchmark::EndEasy],
+        Some("mid_easy") => vec![C4Benchmark::MidEasy],
+        Some("mid_medium") => vec![C4Benchmark::MidMedium],
+        Some("begin_easy") => vec![C4Benchmark::BeginEasy],
+        Some("begin_medium") => vec![C4Benchmark::BeginMedium],
+        Some("begin_hard") => vec![C4Benchmark::BeginHard],
+        Some("all") => vec![
+            C4Benchmark::EndEasy,
+            C4Benchmark::MidEasy,
+            C4Benchmark::MidMedium,
+            C4Benchmark::BeginEasy,
+            C4Benchmark::BeginMedium,
+            C4Benchmark::BeginHard,
+        ],
+        _ => {
+            println!(
+                "usage: c4bench <end_easy|mid_easy|mid_medium|begin_easy|begin_medium|begin_hard|all> [positions] [weak] [book]"
+            );
+            return;
+        }
+    };
+    let mut solver = Connect4Solver::new();
+    let mut max_positions = None;
+    for arg in args.iter().skip(1) {
+        if let Ok(positions) = arg.parse() {
+            max_positions = Some(positions);
+        } else if arg == "weak" {
+            solver.set_weak(true);
+        } else {
+            let Some(book) = C4Book::from_file(arg) else {
+                println!("could not load book {}", arg);
+                return;
+            };
+            println!(
+                "book with {} positions up to ply {}",
+                book.len(),
+                book.max_ply()
+            );
+            solver.set_book(Some(book));
+        }
+    }
+    for benchmark in benchmarks {
+        run_benchmark(benchmark, &mut solver, max_positions);
+    }
+}
+
+fn run_perft_bench() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ] {
+        println!("{}", fen);
+        perft::bench_perft("undo", &mut ThreeCheckBoard::from_fen(fen).unwrap(), 5);
+        perft::bench_perft(
+            "copy-make",
+            &mut ThreeCheckCopyMakeBoard::from_fen(fen).unwrap(),
+            5,
+        );
+    }
+}
+
 fn main() {
-    run_three_check();
-    // perft::run_perft_suite_file::<Connect4Board>("res/c4_perft.txt");
+    match std::env::args().nth(1).as_deref() {
+        Some("ataxx") => run_ataxx(),
+        Some("connect4") => run_connect4(),
+        Some("perftbench") => run_perft_bench(),
+        Some("perft") => run_perft_report(),
+        Some("perftsuite") => run_perft_suite(),
+        Some("perftref") => run_divide_reference(),
+        Some("makebook") => run_make_book(),
+        Some("pgnexport") => run_pgn_export(),
+        Some("pgncheck") => run_pgn_check(),
+        Some("retrograde") => run_retrograde(),
+        Some("tbgen") => run_tablebase_gen(),
+        Some("c4book") => run_c4_book_gen(),
+        Some("c4bench") => run_c4_bench(),
+        Some("c4solve") => run_c4_solve(),
+        _ => run_three_check(),
+    }
     /*let board = TicTacToeBoard::from_fen("3/O2/X2 X").unwrap();
     println!("{}", board);
 
@@ -1,12 +1,20 @@
-use std::fs::File;
-use std::io::prelude::*;
+use core::fmt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::games::board::{Board, GameResult};
+use crate::games::board::{Board, GameResult, HashedBoard};
 
+// counts only positions at exactly the given depth, so finished games add nothing,
+// which is what other engines report
 pub fn perft<const SPLIT: bool, B: Board>(board: &mut B, depth: u32) -> u64 {
-    if depth == 0 || board.game_result() != GameResult::NONE {
+    if depth == 0 {
         return 1;
     }
+    if board.game_result() != GameResult::NONE {
+        return 0;
+    }
 
     let mut nodes = 0;
     for mv in board.gen_moves() {
@@ -22,9 +30,12 @@ pub fn perft<const SPLIT: bool, B: Board>(board: &mut B, depth: u32) -> u64 {
 }
 
 fn perft_impl<B: Board>(board: &mut B, depth: u32) -> u64 {
-    if depth == 0 || board.game_result() != GameResult::NONE {
+    if depth == 0 {
         return 1;
     }
+    if board.game_result() != GameResult::NONE {
+        return 0;
+    }
 
     let mut nodes = 0;
     for mv in board.gen_moves() {
@@ -35,44 +46,607 @@ fn perft_impl<B: Board>(board: &mut B, depth: u32) -> u64 {
     nodes
 }
 
-pub fn run_perft_suite_file<B: Board>(filename: &str) {
-    let mut file = File::open(filename).unwrap();
-    let mut tests = String::new();
-    let _ = file.read_to_string(&mut tests);
-    run_perft_suite::<B>(&tests);
-}
-
-pub fn run_perft_suite<B: Board>(tests: &str) {
-    let stripped = tests.replace('\r', "");
-    let lines = stripped.split("\n");
-    let mut passes = 0;
-    let mut fails = 0;
-    for line in lines {
-        let parts: Vec<&str> = line.split(';').collect();
-        let fen = parts[0];
-        let mut board = B::from_fen(fen).unwrap();
-        let mut failed = false;
-        for test in parts.into_iter().skip(1) {
-            let mut depth_nodes = test.split(' ');
-            let depth = depth_nodes.next().unwrap().parse().unwrap();
-            let a = depth_nodes.next().unwrap();
-            let exp_nodes = a.parse::<u64>().unwrap();
-
-            let actual_nodes = perft::<false, B>(&mut board, depth);
-            if exp_nodes == actual_nodes {
-                passes += 1;
-            } else {
-                failed = true;
-                fails += 1;
-                println!(
-                    "Failed: {} depth {} Expected {} got {}",
-                    fen, depth, exp_nodes, actual_nodes
-                );
+struct PerftEntry {
+    // xored with data so that a torn write from another thread fails the key check
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// shared between threads without locking, always replaces
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / size_of::<PerftEntry>()).max(1);
+        Self {
+            entries: (0..len)
+                .map(|_| PerftEntry {
+                    key: AtomicU64::new(0),
+                    data: AtomicU64::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    // the same position is stored separately for every depth
+    fn index_key(key: u64, depth: u32) -> u64 {
+        key ^ (depth as u64).wrapping_mul(0x9E3779B97F4A7C15)
+    }
+
+    fn probe(&self, key: u64, depth: u32) -> Option<u64> {
+        let key = Self::index_key(key, depth);
+        let entry = &self.entries[(key % self.entries.len() as u64) as usize];
+        let data = entry.data.load(Ordering::Relaxed);
+        if entry.key.load(Ordering::Relaxed) ^ data == key && (data & 0xFF) as u32 == depth {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    fn store(&self, key: u64, depth: u32, nodes: u64) {
+        let key = Self::index_key(key, depth);
+        let entry = &self.entries[(key % self.entries.len() as u64) as usize];
+        let data = (nodes << 8) | depth as u64;
+        entry.key.store(key ^ data, Ordering::Relaxed);
+        entry.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// same counts as perft, but the last ply is counted from the move list instead of being played
+fn bulk_perft<B: Board>(
+    board: &mut B,
+    depth: u32,
+    table: Option<&PerftTable>,
+    key: &impl Fn(&B) -> u64,
+) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if board.game_result() != GameResult::NONE {
+        return 0;
+    }
+    if depth == 1 {
+        return board.gen_moves().into_iter().count() as u64;
+    }
+
+    let hash = table.map(|table| (table, key(board)));
+    if let Some((table, hash)) = hash
+        && let Some(nodes) = table.probe(hash, depth)
+    {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+    for mv in board.gen_moves() {
+        board.make_move(mv);
+        nodes += bulk_perft(board, depth - 1, table, key);
+        board.unmake_move();
+    }
+
+    if let Some((table, hash)) = hash {
+        table.store(hash, depth, nodes);
+    }
+    nodes
+}
+
+// hands out root moves one at a time, so threads that finish early pick up the rest
+fn split_perft<B: Board + Sync>(
+    board: &B,
+    depth: u32,
+    threads: usize,
+    table: Option<&PerftTable>,
+    key: &(impl Fn(&B) -> u64 + Sync),
+) -> u64
+where
+    B::Move: Sync,
+{
+    if threads <= 1 || depth <= 1 || board.game_result() != GameResult::NONE {
+        return bulk_perft(&mut board.clone(), depth, table, key);
+    }
+
+    let moves: Vec<B::Move> = board.gen_moves().into_iter().collect();
+    let next = AtomicUsize::new(0);
+    let nodes = AtomicU64::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads.min(moves.len()) {
+            s.spawn(|| {
+                let mut board = board.clone();
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx >= moves.len() {
+                        break;
+                    }
+                    board.make_move(moves[idx]);
+                    let sub_nodes = bulk_perft(&mut board, depth - 1, table, key);
+                    nodes.fetch_add(sub_nodes, Ordering::Relaxed);
+                    board.unmake_move();
+                }
+            });
+        }
+    });
+    nodes.into_inner()
+}
+
+pub fn fast_perft<B: Board + Sync>(board: &B, depth: u32, threads: usize) -> u64
+where
+    B::Move: Sync,
+{
+    split_perft(board, depth, threads, None, &|_| 0)
+}
+
+pub fn hashed_perft<B: HashedBoard + Sync>(
+    board: &B,
+    depth: u32,
+    threads: usize,
+    table: &PerftTable,
+) -> u64
+where
+    B::Move: Sync,
+{
+    split_perft(board, depth, threads, Some(table), &B::hash_key)
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// runs every depth up to max_depth with the given perft and prints how long each took
+pub fn perft_report<B: Board>(board: &B, max_depth: u32, perft: impl Fn(&B, u32) -> u64) {
+    let start = Instant::now();
+    let mut total = 0;
+    for depth in 1..=max_depth {
+        let depth_start = Instant::now();
+        let nodes = perft(board, depth);
+        let elapsed = depth_start.elapsed();
+        total += nodes;
+        println!(
+            "depth {} nodes {} time {} ms nps {}",
+            depth,
+            nodes,
+            elapsed.as_millis(),
+            (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64
+        );
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "total nodes {} time {} ms nps {}",
+        total,
+        elapsed.as_millis(),
+        (total as f64 / elapsed.as_secs_f64().max(1e-9)) as u64
+    );
+}
+
+// prints the leaf count under every root move in the same format as stockfish's go perft,
+// so the output can be diffed against other engines move by move
+pub fn perft_divide<B: Board>(
+    board: &mut B,
+    depth: u32,
+    move_str: impl Fn(B::Move) -> String,
+) -> u64 {
+    let mut nodes = 0;
+    if depth == 0 {
+        nodes = 1;
+    } else if board.game_result() == GameResult::NONE {
+        for mv in board.gen_moves() {
+            board.make_move(mv);
+            let sub_nodes = perft_impl(board, depth - 1);
+            board.unmake_move();
+            println!("{}: {}", move_str(mv), sub_nodes);
+            nodes += sub_nodes;
+        }
+    }
+    println!();
+    println!("Nodes searched: {}", nodes);
+    nodes
+}
+
+// for comparing the speed of board implementations
+pub fn bench_perft<B: Board>(name: &str, board: &mut B, depth: u32) {
+    let start = Instant::now();
+    let nodes = perft::<false, B>(board, depth);
+    let elapsed = start.elapsed();
+    println!(
+        "{}: {} nodes in {} ms, {} nps",
+        name,
+        nodes,
+        elapsed.as_millis(),
+        (nodes as f64 / elapsed.as_secs_f64()) as u64
+    );
+}
+
+// expected counts under every move of a position, used to narrow a failing suite entry down
+// to a single move. positions are identified by the suite fen, the depth of the failing test
+// and the moves played from it, one per line as fen;depth;moves played;move nodes;move nodes...
+#[derive(Debug, Clone, Default)]
+pub struct DivideReference {
+    entries: HashMap<(String, u32, String), Vec<(String, u64)>>,
+}
+
+impl DivideReference {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut result = Self::default();
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| format!("line {}: {}", line_idx + 1, msg);
+
+            let mut parts = line.split(';');
+            let fen = parts.next().unwrap().trim().to_string();
+            let depth = parts
+                .next()
+                .and_then(|depth| depth.trim().parse().ok())
+                .ok_or_else(|| error("bad depth"))?;
+            let path = parts.next().ok_or_else(|| error("missing move path"))?;
+            let path = path.split_whitespace().collect::<Vec<_>>().join(" ");
+            let mut counts = Vec::new();
+            for part in parts {
+                let mut move_nodes = part.split_whitespace();
+                match (
+                    move_nodes.next(),
+                    move_nodes.next().and_then(|n| n.parse().ok()),
+                ) {
+                    (Some(mv), Some(nodes)) => counts.push((mv.to_string(), nodes)),
+                    _ => return Err(error("bad move count")),
+                }
+            }
+            result.entries.insert((fen, depth, path), counts);
+        }
+        Ok(result)
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(filename).map_err(|err| err.to_string())?;
+        Self::parse(&text)
+    }
+
+    fn get(&self, fen: &str, depth: u32, path: &[String]) -> Option<&Vec<(String, u64)>> {
+        self.entries.get(&(fen.to_string(), depth, path.join(" ")))
+    }
+}
+
+// the first move whose count differs from the reference, after the moves in path
+#[derive(Debug, Clone)]
+pub struct DivideMismatch {
+    pub path: Vec<String>,
+    pub mv: String,
+    pub expected: Option<u64>,
+    pub actual: Option<u64>,
+}
+
+impl fmt::Display for DivideMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "after {}: ", self.path.join(" "))?;
+        }
+        match (self.expected, self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "{} expected {} got {}", self.mv, expected, actual)
             }
+            (Some(_), None) => write!(f, "{} is missing", self.mv),
+            _ => write!(f, "{} should not be legal", self.mv),
+        }
+    }
+}
+
+fn divide_counts<B: Board + Sync>(
+    board: &mut B,
+    depth: u32,
+    threads: usize,
+    move_str: fn(&B, B::Move) -> String,
+) -> Vec<(String, u64)>
+where
+    B::Move: Sync,
+{
+    if board.game_result() != GameResult::NONE {
+        return Vec::new();
+    }
+    let mut counts = Vec::new();
+    for mv in board.gen_moves() {
+        let mv_str = move_str(board, mv);
+        board.make_move(mv);
+        counts.push((mv_str, fast_perft(board, depth - 1, threads)));
+        board.unmake_move();
+    }
+    counts
+}
+
+// follows differing counts down the tree for as long as the reference has entries
+fn find_mismatch<B: Board + Sync>(
+    board: &mut B,
+    fen: &str,
+    test_depth: u32,
+    path: &mut Vec<String>,
+    reference: &DivideReference,
+    move_str: fn(&B, B::Move) -> String,
+    threads: usize,
+) -> Option<DivideMismatch>
+where
+    B::Move: Sync,
+{
+    let depth = test_depth - path.len() as u32;
+    let expected = reference.get(fen, test_depth, path)?;
+    if depth == 0 {
+        return None;
+    }
+    let actual = divide_counts(board, depth, threads, move_str);
+    let mismatch = |mv: &str, expected, actual| DivideMismatch {
+        path: path.clone(),
+        mv: mv.to_string(),
+        expected,
+        actual,
+    };
+
+    if let Some((mv, nodes)) = actual
+        .iter()
+        .find(|(mv, _)| !expected.iter().any(|(exp_mv, _)| exp_mv == mv))
+    {
+        return Some(mismatch(mv, None, Some(*nodes)));
+    }
+    for (mv, exp_nodes) in expected {
+        let Some((_, nodes)) = actual.iter().find(|(act_mv, _)| act_mv == mv) else {
+            return Some(mismatch(mv, Some(*exp_nodes), None));
+        };
+        if nodes == exp_nodes {
+            continue;
+        }
+
+        let result = mismatch(mv, Some(*exp_nodes), Some(*nodes));
+        let child = board
+            .gen_moves()
+            .into_iter()
+            .find(|child| move_str(board, *child) == *mv)
+            .unwrap();
+        board.make_move(child);
+        path.push(mv.clone());
+        let deeper = find_mismatch(board, fen, test_depth, path, reference, move_str, threads);
+        path.pop();
+        board.unmake_move();
+        return Some(deeper.unwrap_or(result));
+    }
+    None
+}
+
+// the reference lines for every position up to levels moves deep, for recording the
+// counts of a known good build
+pub fn divide_reference_lines<B: Board + Sync>(
+    board: &mut B,
+    fen: &str,
+    depth: u32,
+    levels: u32,
+    move_str: fn(&B, B::Move) -> String,
+) -> Vec<String>
+where
+    B::Move: Sync,
+{
+    fn visit<B: Board + Sync>(
+        board: &mut B,
+        fen: &str,
+        test_depth: u32,
+        path: &mut Vec<String>,
+        levels: u32,
+        move_str: fn(&B, B::Move) -> String,
+        lines: &mut Vec<String>,
+    ) where
+        B::Move: Sync,
+    {
+        let depth = test_depth - path.len() as u32;
+        if depth == 0 {
+            return;
+        }
+        let counts = divide_counts(board, depth, default_threads(), move_str);
+        let mut line = format!("{};{};{}", fen, test_depth, path.join(" "));
+        for (mv, nodes) in counts.iter() {
+            line += &format!(";{} {}", mv, nodes);
         }
-        if !failed {
-            println!("Passed {}", fen);
+        lines.push(line);
+
+        if path.len() as u32 + 1 >= levels {
+            return;
+        }
+        for mv in board.gen_moves() {
+            path.push(move_str(board, mv));
+            board.make_move(mv);
+            visit(board, fen, test_depth, path, levels, move_str, lines);
+            board.unmake_move();
+            path.pop();
+        }
+    }
+
+    let mut lines = Vec::new();
+    visit(
+        board,
+        fen,
+        depth,
+        &mut Vec::new(),
+        levels,
+        move_str,
+        &mut lines,
+    );
+    lines
+}
+
+#[derive(Debug, Clone)]
+pub struct PerftTestResult {
+    pub line: usize,
+    pub fen: String,
+    pub depth: u32,
+    pub expected: u64,
+    pub actual: u64,
+    pub time: Duration,
+    pub mismatch: Option<DivideMismatch>,
+}
+
+impl PerftTestResult {
+    pub fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PerftSuiteReport {
+    pub results: Vec<PerftTestResult>,
+    // malformed lines, the rest of the suite still runs
+    pub errors: Vec<String>,
+    // tests left out by the depth or time limit
+    pub skipped: usize,
+    pub time: Duration,
+}
+
+impl PerftSuiteReport {
+    pub fn passes(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &PerftTestResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.errors.is_empty() && self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for PerftSuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in self.results.iter() {
+            writeln!(
+                f,
+                "{} line {} depth {}: {} expected {} got {} in {} ms",
+                if result.passed() { "Passed" } else { "Failed" },
+                result.line,
+                result.depth,
+                result.fen,
+                result.expected,
+                result.actual,
+                result.time.as_millis()
+            )?;
+            if let Some(mismatch) = &result.mismatch {
+                writeln!(f, "    first difference {}", mismatch)?;
+            }
+        }
+        for error in self.errors.iter() {
+            writeln!(f, "Error: {}", error)?;
+        }
+        write!(
+            f,
+            "Passed: {} / {}, {} skipped, {} errors, {} ms",
+            self.passes(),
+            self.results.len(),
+            self.skipped,
+            self.errors.len(),
+            self.time.as_millis()
+        )
+    }
+}
+
+fn debug_move_str<B: Board>(_: &B, mv: B::Move) -> String {
+    format!("{:?}", mv)
+}
+
+pub struct PerftSuiteOptions<B: Board> {
+    pub max_depth: Option<u32>,
+    // no new test starts once the suite has run for this long
+    pub max_time: Option<Duration>,
+    pub threads: usize,
+    pub reference: Option<DivideReference>,
+    // has to match how the reference writes moves
+    pub move_str: fn(&B, B::Move) -> String,
+}
+
+impl<B: Board> Default for PerftSuiteOptions<B> {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_time: None,
+            threads: default_threads(),
+            reference: None,
+            move_str: debug_move_str::<B>,
+        }
+    }
+}
+
+// suite lines look like fen;depth nodes;depth nodes..., blank lines and lines starting with # are skipped
+pub fn perft_suite_report<B: Board + Sync>(
+    tests: &str,
+    from_fen: impl Fn(&str) -> Option<B>,
+    options: &PerftSuiteOptions<B>,
+) -> PerftSuiteReport
+where
+    B::Move: Sync,
+{
+    let start = Instant::now();
+    let mut report = PerftSuiteReport::default();
+    for (line_idx, line) in tests.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_num = line_idx + 1;
+
+        let mut parts = line.split(';');
+        let fen = parts.next().unwrap().trim();
+        let Some(mut board) = from_fen(fen) else {
+            report
+                .errors
+                .push(format!("line {}: invalid fen {}", line_num, fen));
+            continue;
+        };
+
+        for test in parts {
+            let mut depth_nodes = test.split_whitespace();
+            let depth = depth_nodes
+                .next()
+                .and_then(|depth| depth.parse::<u32>().ok());
+            let expected = depth_nodes
+                .next()
+                .and_then(|nodes| nodes.parse::<u64>().ok());
+            let (Some(depth), Some(expected)) = (depth, expected) else {
+                report
+                    .errors
+                    .push(format!("line {}: bad test '{}'", line_num, test));
+                continue;
+            };
+
+            if options.max_depth.is_some_and(|max_depth| depth > max_depth)
+                || options
+                    .max_time
+                    .is_some_and(|max_time| start.elapsed() >= max_time)
+            {
+                report.skipped += 1;
+                continue;
+            }
+
+            let test_start = Instant::now();
+            let actual = fast_perft(&board, depth, options.threads);
+            let time = test_start.elapsed();
+            let mismatch = match &options.reference {
+                Some(reference) if actual != expected => find_mismatch(
+                    &mut board,
+                    fen,
+                    depth,
+                    &mut Vec::new(),
+                    reference,
+                    options.move_str,
+                    options.threads,
+                ),
+                _ => None,
+            };
+            report.results.push(PerftTestResult {
+                line: line_num,
+                fen: fen.to_string(),
+                depth,
+                expected,
+                actual,
+                time,
+                mismatch,
+            });
         }
     }
-    println!("Passed: {} / {}", passes, passes + fails);
+    report.time = start.elapsed();
+    report
 }
@@ -0,0 +1,289 @@
+use std::time::{Duration, Instant};
+
+use arrayvec::ArrayVec;
+
+use crate::{
+    eval::{AtaxxEval, Eval},
+    games::{
+        ataxx::{AtaxxBoard, AtaxxMove},
+        board::{Board, GameResult},
+    },
+};
+
+use super::{
+    search::{Search, SearchLimits, SearchResult},
+    tt::{TT, TTBound, decisive_score_from_tt, decisive_score_to_tt},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TTEntry {
+    mv: Option<AtaxxMove>,
+    score: i16,
+    depth: u8,
+    bound: TTBound,
+}
+
+impl TTEntry {
+    fn adjust_from_tt(&mut self, ply: i32) {
+        if self.score.abs() as i32 >= AtaxxSearch::SCORE_WIN - 256 {
+            self.score = decisive_score_from_tt(self.score as i32, ply) as i16;
+        }
+    }
+
+    fn to_tt(&self, ply: i32) -> Self {
+        let mut result = *self;
+        if result.score.abs() as i32 >= AtaxxSearch::SCORE_WIN - 256 {
+            result.score = decisive_score_to_tt(self.score as i32, ply) as i16;
+        }
+        result
+    }
+}
+
+pub struct AtaxxSearch {
+    nodes: u64,
+    root_best_move: Option<AtaxxMove>,
+    root_depth: i32,
+    stop: bool,
+    start_time: Instant,
+    limits: SearchLimits,
+    tt: TT<TTEntry>,
+    eval: AtaxxEval,
+}
+
+impl AtaxxSearch {
+    const SCORE_WIN: i32 = 32000;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: 0,
+            root_best_move: None,
+            root_depth: 0,
+            stop: false,
+            start_time: Instant::now(),
+            limits: SearchLimits::default(),
+            tt: TT::new(16),
+            eval: AtaxxEval::default(),
+        }
+    }
+
+    fn score_move(&self, board: &AtaxxBoard, mv: AtaxxMove, tt_move: Option<AtaxxMove>) -> i32 {
+        if Some(mv) == tt_move {
+            return 1000000;
+        }
+        // a single move adds a piece, a double move only relocates one
+        let captures = board.curr_state().captures(mv) as i32;
+        4 * captures + mv.is_single() as i32 * 3
+    }
+
+    fn order_moves(
+        &self,
+        board: &AtaxxBoard,
+        moves: &mut ArrayVec<AtaxxMove, 256>,
+        tt_move: Option<AtaxxMove>,
+    ) {
+        moves.sort_by_key(|mv: &AtaxxMove| -self.score_move(board, *mv, tt_move));
+    }
+
+    fn should_stop(&mut self) -> bool {
+        // always finish depth 1 so there is a move to play
+        if self.root_depth <= 1 || self.nodes % 1024 != 0 {
+            return false;
+        }
+        if let Some(max_nodes) = self.limits.max_nodes {
+            if self.nodes >= max_nodes {
+                return true;
+            }
+        }
+        if let Some(max_time) = self.limits.max_time {
+            if Instant::now() - self.start_time > Duration::from_millis(max_time) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn alpha_beta<const PV: bool>(
+        &mut self,
+        board: &mut AtaxxBoard,
+        depth: i32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.should_stop() {
+            self.stop = true;
+            return 0;
+        }
+
+        match board.game_result() {
+            GameResult::WIN => return Self::SCORE_WIN - ply,
+            GameResult::DRAW => return 0,
+            GameResult::LOSS => return -Self::SCORE_WIN + ply,
+            _ => {}
+        }
+
+        let key = board.curr_state().key();
+        let tt_entry = self.tt.probe(key).map(|mut entry| {
+            entry.adjust_from_tt(ply);
+            entry
+        });
+
+        // the clock decides draws but is not in the key, so entries are only trusted where it
+        // cannot reach 100 within their depth
+        let clock = board.curr_state().half_move_clock() as i32;
+        if !PV {
+            if let Some(entry) = tt_entry {
+                if entry.depth as i32 >= depth
+                    && clock + (entry.depth as i32) < 100
+                    && (entry.bound == TTBound::EXACT
+                        || (entry.bound == TTBound::UPPER && entry.score as i32 <= alpha)
+                        || (entry.bound == TTBound::LOWER && entry.score as i32 >= beta))
+                {
+                    return entry.score as i32;
+                }
+            }
+        }
+
+        if depth <= 0 {
+            return self.eval.evaluate(board);
+        }
+
+        let mut moves = board.gen_moves();
+        self.order_moves(board, &mut moves, tt_entry.and_then(|tte| tte.mv));
+
+        let mut best_score = -Self::SCORE_WIN;
+        let mut best_move = None;
+        let mut tt_bound = TTBound::UPPER;
+        let mut moves_played = 0;
+
+        for mv in moves.iter() {
+            let mv = *mv;
+            board.make_move(mv);
+            self.nodes += 1;
+            moves_played += 1;
+
+            let mut score = 0;
+            if !PV || moves_played > 1 {
+                score = -self.alpha_beta::<false>(board, depth - 1, ply + 1, -alpha - 1, -alpha);
+            }
+            if PV && (moves_played == 1 || score > alpha) {
+                score = -self.alpha_beta::<true>(board, depth - 1, ply + 1, -beta, -alpha);
+            }
+
+            board.unmake_move();
+            if self.stop {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                tt_bound = TTBound::EXACT;
+
+                if ply == 0 {
+                    self.root_best_move = Some(mv);
+                }
+            }
+
+            if score >= beta {
+                tt_bound = TTBound::LOWER;
+                break;
+            }
+        }
+
+        if clock + depth >= 100 {
+            return best_score;
+        }
+        self.tt.store(
+            key,
+            TTEntry {
+                mv: best_move,
+                depth: depth as u8,
+                score: best_score as i16,
+                bound: tt_bound,
+            }
+            .to_tt(ply),
+        );
+
+        best_score
+    }
+}
+
+impl Search<AtaxxBoard> for AtaxxSearch {
+    fn search(&mut self, board: &AtaxxBoard, limits: SearchLimits) -> SearchResult<AtaxxBoard> {
+        self.nodes = 0;
+        self.root_best_move = None;
+        self.stop = false;
+        let mut tmp_board = board.clone();
+
+        self.start_time = Instant::now();
+        // there is nothing to search once the game is over, so the null move is played
+        let score = match board.game_result() {
+            GameResult::WIN => Some(Self::SCORE_WIN),
+            GameResult::DRAW => Some(0),
+            GameResult::LOSS => Some(-Self::SCORE_WIN),
+            _ => None,
+        };
+        if let Some(score) = score {
+            return SearchResult {
+                nodes: 0,
+                time: Duration::ZERO,
+                best_move: AtaxxMove::null(),
+                score,
+                pv: Vec::new(),
+            };
+        }
+
+        self.limits = limits;
+        let mut score = 0;
+        let mut max_depth = 64;
+        // depth 1 always runs, even for depth 0, so there is a move to play
+        if let Some(max) = limits.max_depth {
+            max_depth = max_depth.min(max.max(1) as i32 + 1);
+        }
+        let mut best_move = None;
+        for depth in 1..max_depth {
+            self.root_depth = depth;
+            let iter_score = self.alpha_beta::<true>(
+                &mut tmp_board,
+                depth,
+                0,
+                -Self::SCORE_WIN,
+                Self::SCORE_WIN,
+            );
+            if self.stop {
+                break;
+            }
+
+            score = iter_score;
+            best_move = self.root_best_move;
+            let elapsed = Instant::now() - self.start_time;
+            println!(
+                "info depth {} nodes {} time {} score cp {} nps {} pv {}",
+                depth,
+                self.nodes,
+                elapsed.as_millis(),
+                score,
+                (self.nodes as f64 / elapsed.as_secs_f64()) as i32,
+                best_move.unwrap()
+            );
+        }
+        let end_time = Instant::now();
+
+        SearchResult {
+            nodes: self.nodes,
+            time: end_time - self.start_time,
+            best_move: best_move.unwrap(),
+            score: score,
+            pv: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tt.clear();
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::games::{
+    board::{CopyMakeBoard, GameResult},
+    connect4::{Connect4Board, Connect4State},
+};
+
+use super::{
+    c4_solver::Connect4Solver,
+    search::{Search, SearchLimits},
+};
+
+const MAGIC: &[u8; 8] = b"calc4bk\0";
+const HEADER_SIZE: usize = 16;
+
+// entries are the symmetric position code shifted up by 8 with the score in the low byte, so
+// sorting the entries sorts them by position
+const SCORE_BITS: u32 = 8;
+
+// plies to the end of the game, negative for losses and 0 for draws
+fn encode_score(score: i32) -> u8 {
+    let distance = if score > 0 {
+        <Connect4Solver>::SCORE_WIN - score
+    } else if score < 0 {
+        -(<Connect4Solver>::SCORE_WIN + score)
+    } else {
+        0
+    };
+    distance as i8 as u8
+}
+
+fn decode_score(byte: u8) -> i32 {
+    let distance = byte as i8 as i32;
+    if distance > 0 {
+        <Connect4Solver>::SCORE_WIN - distance
+    } else if distance < 0 {
+        -<Connect4Solver>::SCORE_WIN - distance
+    } else {
+        0
+    }
+}
+
+// exact solver scores for every position up to some number of pieces, with scores from the side
+// to move's view as if the position was the root. mirrored positions share an entry
+#[derive(Debug, Clone)]
+pub struct C4Book<const COLUMNS: u8 = 7, const ROWS: u8 = 6> {
+    max_ply: u32,
+    entries: Vec<u64>,
+}
+
+impl<const COLUMNS: u8, const ROWS: u8> C4Book<COLUMNS, ROWS> {
+    pub fn new(max_ply: u32) -> Self {
+        // the position code has a bit per square plus one per column, and has to fit above the score
+        const {
+            assert!(
+                COLUMNS as u32 * (ROWS as u32 + 1) <= u64::BITS - SCORE_BITS,
+                "board too large for connect 4 book entries"
+            )
+        };
+        Self {
+            max_ply,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn max_ply(&self) -> u32 {
+        self.max_ply
+    }
+
+    fn find(&self, code: u64) -> Option<u64> {
+        let start = self
+            .entries
+            .partition_point(|entry| entry >> SCORE_BITS < code);
+        self.entries
+            .get(start)
+            .filter(|entry| *entry >> SCORE_BITS == code)
+            .copied()
+    }
+
+    pub fn probe(&self, state: &Connect4State<COLUMNS, ROWS>) -> Option<i32> {
+        if state.occ().popcount() > self.max_ply {
+            return None;
+        }
+        let entry = self.find(state.symmetric_code())?;
+        Some(decode_score(entry as u8))
+    }
+
+    // entries can be added in any order, but probing only sees them after sort
+    fn insert(&mut self, state: &Connect4State<COLUMNS, ROWS>, score: i32) {
+        let code = state.symmetric_code();
+        self.entries
+            .push((code << SCORE_BITS) | encode_score(score) as u64);
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_unstable();
+        self.entries.dedup();
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return None;
+        }
+        if (bytes[9], bytes[10]) != (COLUMNS, ROWS) {
+            return None;
+        }
+        let body = &bytes[HEADER_SIZE..];
+        if !body.len().is_multiple_of(8) {
+            return None;
+        }
+        let mut book = Self::new(bytes[8] as u32);
+        book.entries = body
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        book.sort();
+        Some(book)
+    }
+
+    pub fn from_file(filename: &str) -> Option<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename).ok()?.read_to_end(&mut bytes).ok()?;
+        Self::from_bytes(&bytes)
+    }
+
+    // the header holds the ply the book goes up to and the board size, then come the entries as
+    // little endian u64s
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + 8 * self.entries.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.max_ply as u8);
+        bytes.push(COLUMNS);
+        bytes.push(ROWS);
+        bytes.resize(HEADER_SIZE, 0);
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        File::create(filename)?.write_all(&self.to_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct C4BookLayer {
+    pub ply: u32,
+    pub positions: usize,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+// solves every position reachable from root with at most max_ply pieces. the deepest layer is
+// solved first, so every shallower position only has to look its children up in the book.
+// progress is called after each layer
+pub fn generate_book<const COLUMNS: u8, const ROWS: u8>(
+    root: &Connect4Board<COLUMNS, ROWS>,
+    max_ply: u32,
+    mut progress: impl FnMut(C4BookLayer),
+) -> C4Book<COLUMNS, ROWS> {
+    let root_ply = root.curr_state().occ().popcount();
+    let mut layers: Vec<Vec<Connect4State<COLUMNS, ROWS>>> = vec![vec![root.curr_state().clone()]];
+    let mut seen = HashSet::new();
+    for _ in root_ply..max_ply {
+        let mut next = Vec::new();
+        for state in layers.last().unwrap().iter() {
+            for mv in state.gen_moves() {
+                let mut child = state.clone();
+                child.make_move(mv);
+                if child.game_result() == GameResult::NONE && seen.insert(child.symmetric_code()) {
+                    next.push(child);
+                }
+            }
+        }
+        layers.push(next);
+    }
+
+    let mut book = C4Book::new(max_ply);
+    let mut solver = Connect4Solver::<COLUMNS, ROWS>::new();
+    for (depth, layer) in layers.iter().enumerate().rev() {
+        let start = Instant::now();
+        let mut nodes = 0;
+        solver.set_book(Some(book.clone()));
+        for state in layer.iter() {
+            let result = solver.search(
+                &Connect4Board::from_state(state.clone()),
+                SearchLimits::default(),
+            );
+            nodes += result.nodes;
+            book.insert(state, result.score);
+        }
+        book.sort();
+        progress(C4BookLayer {
+            ply: root_ply + depth as u32,
+            positions: layer.len(),
+            nodes,
+            time: start.elapsed(),
+        });
+    }
+    book
+}
@@ -6,21 +6,25 @@ use std::{
 
 use crate::games::{
     board::{Board, GameResult},
-    connect4::{Connect4Board, Connect4Move},
+    connect4::{Connect4Board, Connect4Move, MAX_COLUMNS},
 };
 
 use super::{
+    c4_book::C4Book,
     search::{Search, SearchLimits, SearchResult},
     tt::{TT, TTBound, decisive_score_from_tt, decisive_score_to_tt},
 };
 
+// entries are shared with the mirrored position, the move is stored as it is played in the
+// position the key belongs to
 #[derive(Default, Clone)]
-pub struct C4TTEntry {
+pub struct C4TTEntry<const COLUMNS: u8 = 7, const ROWS: u8 = 6> {
     score: i32,
     bound: TTBound,
+    mv: Option<Connect4Move<COLUMNS, ROWS>>,
 }
 
-impl C4TTEntry {
+impl<const COLUMNS: u8, const ROWS: u8> C4TTEntry<COLUMNS, ROWS> {
     fn adjust_from_tt(&mut self, ply: i32) {
         // non zero scores are terminal
         if self.score != 0 {
@@ -39,46 +43,97 @@ impl C4TTEntry {
 
 use arrayvec::ArrayVec;
 
-pub struct Connect4Solver {
+pub(super) fn score_move<const COLUMNS: u8, const ROWS: u8>(
+    board: &Connect4Board<COLUMNS, ROWS>,
+    mv: Connect4Move<COLUMNS, ROWS>,
+) -> i32 {
+    let col = mv.sq().column();
+    let row = mv.sq().row();
+    // twice the distance to the center so boards with an even number of columns work too
+    let center_distance = (2 * col).abs_diff(COLUMNS - 1) as i32;
+    let base_score =
+        -3 * center_distance / 2 - (row.abs_diff(ROWS / 2) as i32) + 5 * (row % 2 == 1) as i32;
+
+    let threats_after = board.curr_state().our_threats_after(mv);
+    let moves_after = board.curr_state().move_locations_after(mv);
+    let double_threat = (threats_after & moves_after).multiple()
+        || (threats_after & threats_after.south() & moves_after).any();
+    base_score
+        + 20 * threats_after.popcount() as i32
+        + 30 * (threats_after & moves_after).popcount() as i32
+        + 100 * double_threat as i32
+}
+
+// one null window search of the score bisection, the score is a bound
+#[derive(Debug, Clone, Copy)]
+pub struct C4SolveIteration {
+    pub alpha: i32,
+    pub beta: i32,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+pub struct Connect4Solver<const COLUMNS: u8 = 7, const ROWS: u8 = 6> {
     nodes: u64,
-    root_best_move: Option<Connect4Move>,
-    tt: TT<C4TTEntry>,
+    root_best_move: Option<Connect4Move<COLUMNS, ROWS>>,
+    tt: TT<C4TTEntry<COLUMNS, ROWS>>,
+    book: Option<C4Book<COLUMNS, ROWS>>,
+    // only tell wins, draws and losses apart, scores are 1, 0 and -1
+    weak: bool,
+    iterations: Vec<C4SolveIteration>,
 }
 
-impl Connect4Solver {
-    const SCORE_WIN: i32 = 1000;
+impl<const COLUMNS: u8, const ROWS: u8> Connect4Solver<COLUMNS, ROWS> {
+    pub(super) const SCORE_WIN: i32 = 1000;
 
     pub fn new() -> Self {
         Self {
             nodes: 0,
             root_best_move: None,
             tt: TT::new(32),
+            book: None,
+            weak: false,
+            iterations: Vec::new(),
         }
     }
 
-    fn score_move(&mut self, board: &mut Connect4Board, mv: Connect4Move) -> i32 {
-        let col = mv.sq().column();
-        let row = mv.sq().row();
-        let base_score =
-            -3 * (col.abs_diff(3) as i32) - (row.abs_diff(3) as i32) + 5 * (row % 2 == 1) as i32;
-
-        let threats_after = board.curr_state().our_threats_after(mv);
-        let moves_after = board.curr_state().move_locations_after(mv);
-        let double_threat = (threats_after & moves_after).multiple()
-            || (threats_after & threats_after.south() & moves_after).any();
-        base_score
-            + 20 * threats_after.popcount() as i32
-            + 30 * (threats_after & moves_after).popcount() as i32
-            + 100 * double_threat as i32
+    pub fn set_book(&mut self, book: Option<C4Book<COLUMNS, ROWS>>) {
+        self.book = book;
+    }
+
+    pub fn set_weak(&mut self, weak: bool) {
+        self.weak = weak;
     }
 
-    fn order_moves(&mut self, board: &mut Connect4Board, moves: &mut ArrayVec<Connect4Move, 7>) {
-        moves.sort_by_key(|mv: &Connect4Move| -self.score_move(board, *mv));
+    pub fn weak(&self) -> bool {
+        self.weak
+    }
+
+    // the null window searches of the last search in the order they ran
+    pub fn iterations(&self) -> &[C4SolveIteration] {
+        &self.iterations
+    }
+
+    // the tt move goes first, it is the move that failed high or was best last time
+    fn order_moves(
+        &mut self,
+        board: &mut Connect4Board<COLUMNS, ROWS>,
+        moves: &mut ArrayVec<Connect4Move<COLUMNS, ROWS>, MAX_COLUMNS>,
+        tt_move: Option<Connect4Move<COLUMNS, ROWS>>,
+    ) {
+        moves.sort_by_key(|mv: &Connect4Move<COLUMNS, ROWS>| {
+            if Some(*mv) == tt_move {
+                i32::MIN
+            } else {
+                -score_move(board, *mv)
+            }
+        });
     }
 
     fn alpha_beta<const PV: bool>(
         &mut self,
-        board: &mut Connect4Board,
+        board: &mut Connect4Board<COLUMNS, ROWS>,
         ply: i32,
         mut alpha: i32,
         mut beta: i32,
@@ -91,24 +146,52 @@ impl Connect4Solver {
             return alpha;
         }
 
-        // win on the next move
+        let root = ply == 0;
+
+        // book scores are exact, the root still needs to search to find the move
+        if !root
+            && let Some(score) = self
+                .book
+                .as_ref()
+                .and_then(|book| book.probe(board.curr_state()))
+        {
+            return if score == 0 {
+                0
+            } else {
+                decisive_score_from_tt(score, ply)
+            };
+        }
+
+        // win on the next move, the root also needs the move itself
         let move_locations = board.curr_state().move_locations();
-        if (board.curr_state().our_threats() & move_locations).any() {
+        let winning_moves = board.curr_state().our_threats() & move_locations;
+        if winning_moves.any() {
+            if root {
+                self.root_best_move = board
+                    .gen_moves()
+                    .into_iter()
+                    .find(|mv| winning_moves.has(mv.sq()));
+            }
             return Self::SCORE_WIN - (ply + 1);
         }
         // cannot stop the opponent from winning in 2 moves
         let opp_threats = board.curr_state().their_threats();
-        if (opp_threats & move_locations).multiple()
-            || (opp_threats & opp_threats.south() & move_locations).any()
+        if !root
+            && ((opp_threats & move_locations).multiple()
+                || (opp_threats & opp_threats.south() & move_locations).any())
         {
             return -Self::SCORE_WIN + (ply + 2);
         }
 
-        let non_losing_moves = if (opp_threats & move_locations).any() {
+        let mut non_losing_moves = if (opp_threats & move_locations).any() {
             opp_threats & move_locations
         } else {
             move_locations & !opp_threats.south()
         };
+        if root && non_losing_moves.empty() {
+            // lost anyways, but the root still has to return a move
+            non_losing_moves = move_locations;
+        }
 
         match board.game_result() {
             GameResult::WIN => return Self::SCORE_WIN - ply,
@@ -117,20 +200,35 @@ impl Connect4Solver {
             _ => {}
         }
 
-        if let Some(mut data) = self.tt.probe(board.curr_state().key()) {
-            data.adjust_from_tt(ply);
+        // every move lets the opponent win immediately, the root was given every move above
+        if non_losing_moves.empty() {
+            return -Self::SCORE_WIN + (ply + 2);
+        }
 
-            if data.bound == TTBound::EXACT
-                || (data.bound == TTBound::LOWER && data.score >= beta)
-                || (data.bound == TTBound::UPPER && data.score <= alpha)
+        // mirrored positions share an entry, so the tt move is mirrored back when the key is
+        let (key, mirrored) = board.curr_state().canonical_key();
+        let to_board =
+            |mv: Connect4Move<COLUMNS, ROWS>| if mirrored { mv.flip_horizontal() } else { mv };
+        let mut tt_move = None;
+        if let Some(mut data) = self.tt.probe(key) {
+            data.adjust_from_tt(ply);
+            tt_move = data.mv.map(to_board);
+
+            // the root can be in the tt when the tt is kept between searches, and still needs a
+            // move
+            if !root
+                && (data.bound == TTBound::EXACT
+                    || (data.bound == TTBound::LOWER && data.score >= beta)
+                    || (data.bound == TTBound::UPPER && data.score <= alpha))
             {
                 return data.score;
             }
         }
 
         let mut moves = board.gen_moves();
-        self.order_moves(board, &mut moves);
+        self.order_moves(board, &mut moves, tt_move);
         let mut best_score = -Self::SCORE_WIN;
+        let mut best_move = None;
         let mut moves_played = 0;
 
         let mut bound = TTBound::UPPER;
@@ -156,6 +254,7 @@ impl Connect4Solver {
 
             if score > best_score {
                 best_score = score;
+                best_move = Some(mv);
             }
 
             if score > alpha {
@@ -173,10 +272,11 @@ impl Connect4Solver {
         }
 
         self.tt.store(
-            board.curr_state().key(),
+            key,
             C4TTEntry {
                 score: best_score,
-                bound: bound,
+                bound,
+                mv: best_move.map(to_board),
             }
             .to_tt(ply),
         );
@@ -189,25 +289,109 @@ impl Connect4Solver {
     }
 }
 
-impl Search<Connect4Board> for Connect4Solver {
+impl<const COLUMNS: u8, const ROWS: u8> Search<Connect4Board<COLUMNS, ROWS>>
+    for Connect4Solver<COLUMNS, ROWS>
+{
     fn search(
         &mut self,
-        board: &Connect4Board,
+        board: &Connect4Board<COLUMNS, ROWS>,
         _limits: SearchLimits,
-    ) -> SearchResult<Connect4Board> {
+    ) -> SearchResult<Connect4Board<COLUMNS, ROWS>> {
         self.nodes = 0;
         self.root_best_move = None;
+        self.iterations.clear();
+
+        // there is nothing to search once the game is over, so the null move is played
+        let score = match board.game_result() {
+            GameResult::WIN => Some(Self::SCORE_WIN),
+            GameResult::DRAW => Some(0),
+            GameResult::LOSS => Some(-Self::SCORE_WIN),
+            _ => None,
+        };
+        if let Some(score) = score {
+            return SearchResult {
+                nodes: 0,
+                time: Duration::ZERO,
+                best_move: Connect4Move::null(),
+                score: if self.weak { score.signum() } else { score },
+                pv: Vec::new(),
+            };
+        }
+
         let mut tmp_board = board.clone();
 
+        // a win or loss is decided by the time the board is full, so decisive scores are never
+        // closer to 0 than this. we can only win on our own moves and lose on theirs, so wins
+        // are an odd number of plies away and losses an even number
+        let empty = board.curr_state().empty_squares() as i32;
+        let lowest_win = Self::SCORE_WIN - empty;
+        let highest_loss = -Self::SCORE_WIN + empty;
+        let reachable =
+            |score: i32| score == 0 || (Self::SCORE_WIN - score.abs()) % 2 == (score > 0) as i32;
+
+        // bisect the score with null window searches until the bounds meet. 0 is tested first
+        // since it splits wins from losses, then only scores that can be reached are tested
+        let (mut min, mut max) = if self.weak {
+            (-1, 1)
+        } else {
+            (-Self::SCORE_WIN + 2, Self::SCORE_WIN - 1)
+        };
         let start_time = Instant::now();
-        let score = self.alpha_beta::<true>(&mut tmp_board, 0, -Self::SCORE_WIN, Self::SCORE_WIN);
+        while min < max {
+            let med = if min < 0 && max > 0 {
+                0
+            } else if min < 0 && max == 0 {
+                -1
+            } else {
+                // both bounds are reachable here and so are the scores every 2 apart in between,
+                // test whether the score reaches the middle one of those
+                min + 2 * (((max - min) / 2 + 1) / 2) - 1
+            };
+
+            let nodes_before = self.nodes;
+            let iteration_start = Instant::now();
+            let score = self.alpha_beta::<false>(&mut tmp_board, 0, med, med + 1);
+            self.iterations.push(C4SolveIteration {
+                alpha: med,
+                beta: med + 1,
+                score,
+                nodes: self.nodes - nodes_before,
+                time: iteration_start.elapsed(),
+            });
+
+            // bounds are moved onto the nearest score that can be reached
+            if score > med {
+                min = score;
+                if min > 0 {
+                    min = min.max(lowest_win);
+                }
+                if !reachable(min) {
+                    min += 1;
+                }
+            } else {
+                max = score;
+                if max < 0 {
+                    max = max.min(highest_loss);
+                }
+                if !reachable(max) {
+                    max -= 1;
+                }
+            }
+        }
+
+        // the search that failed high on the final score left its move at the root. if every
+        // search failed low the position is lost, so look for the move that holds out longest
+        if self.root_best_move.is_none() {
+            self.alpha_beta::<true>(&mut tmp_board, 0, -Self::SCORE_WIN, min + 1);
+        }
+        let score = if self.weak { min.signum() } else { min };
         let end_time = Instant::now();
 
         SearchResult {
             nodes: self.nodes,
             time: end_time - start_time,
             best_move: self.root_best_move.unwrap(),
-            score: score,
+            score,
             pv: Vec::new(),
         }
     }
@@ -223,7 +407,13 @@ pub enum C4Benchmark {
     BeginHard,
 }
 
-pub fn run_benchmark(benchmark: C4Benchmark) {
+// solves the positions of a benchmark set with the solver as configured, up to max_positions of
+// them. weak solvers only have to get the sign of the score right
+pub fn run_benchmark(
+    benchmark: C4Benchmark,
+    solver: &mut Connect4Solver,
+    max_positions: Option<usize>,
+) {
     let filename = match benchmark {
         C4Benchmark::EndEasy => "res/c4_endgame_easy.txt",
         C4Benchmark::MidEasy => "res/c4_midgame_easy.txt",
@@ -236,16 +426,25 @@ pub fn run_benchmark(benchmark: C4Benchmark) {
     let mut file = File::open(filename).unwrap();
     let mut positions = String::new();
     let _ = file.read_to_string(&mut positions);
+    let count = max_positions.map_or(positions.lines().count(), |max| {
+        max.min(positions.lines().count())
+    });
 
     let mut total_nodes = 0;
     let mut total_time: Duration = Duration::ZERO;
-    let mut solver = Connect4Solver::new();
+    let mut total_iterations = 0;
+    let mut failures = 0;
+    // positions, nodes and time of the nth null window search
+    let mut by_iteration: Vec<(usize, u64, Duration)> = Vec::new();
 
     println!("Running connect 4 benchmark {:?}", benchmark);
-    for (it, line) in positions.lines().enumerate() {
+    for (it, line) in positions.lines().take(count).enumerate() {
         let mut parts = line.split(';');
         let fen = parts.next().unwrap();
-        let expected_score = parts.next().unwrap().parse::<i32>().unwrap();
+        let mut expected_score = parts.next().unwrap().parse::<i32>().unwrap();
+        if solver.weak() {
+            expected_score = expected_score.signum();
+        }
 
         let board = Connect4Board::from_fen(fen).unwrap();
         solver.clear();
@@ -256,22 +455,50 @@ pub fn run_benchmark(benchmark: C4Benchmark) {
         };
         let result = solver.search(&board, limits);
         if result.score != expected_score {
+            failures += 1;
             println!(
                 "Failed: incorrect score {} fen: {} expected score: {}",
                 result.score, fen, expected_score
             );
+            for iteration in solver.iterations() {
+                println!(
+                    "  window ({}, {}) score {} nodes {}",
+                    iteration.alpha, iteration.beta, iteration.score, iteration.nodes
+                );
+            }
         }
         total_nodes += result.nodes;
         total_time += result.time;
+        total_iterations += solver.iterations().len();
+        for (i, iteration) in solver.iterations().iter().enumerate() {
+            if by_iteration.len() <= i {
+                by_iteration.push((0, 0, Duration::ZERO));
+            }
+            by_iteration[i].0 += 1;
+            by_iteration[i].1 += iteration.nodes;
+            by_iteration[i].2 += iteration.time;
+        }
         if it % 32 == 0 {
-            println!("{} / 1000 done", it);
+            println!("{} / {} done", it, count);
         }
     }
+    let count = count.max(1);
     println!("Finished connect 4 benchmark {:?}", benchmark);
     println!(
-        "Average time: {}\nAverage nodes: {}\nAverage nps: {}",
-        total_time.as_secs_f64() / 1000.0,
-        total_nodes / 1000,
+        "Failures: {}\nAverage time: {}\nAverage nodes: {}\nAverage iterations: {}\nAverage nps: {}",
+        failures,
+        total_time.as_secs_f64() / count as f64,
+        total_nodes / count as u64,
+        total_iterations as f64 / count as f64,
         total_nodes as f64 / total_time.as_secs_f64()
     );
+    for (i, (positions, nodes, time)) in by_iteration.iter().enumerate() {
+        println!(
+            "Iteration {}: {} positions, average nodes {}, average time {}",
+            i + 1,
+            positions,
+            nodes / *positions as u64,
+            time.as_secs_f64() / *positions as f64
+        );
+    }
 }
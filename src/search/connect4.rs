@@ -0,0 +1,336 @@
+use std::time::{Duration, Instant};
+
+use arrayvec::ArrayVec;
+
+use crate::{
+    eval::{Connect4Eval, Eval},
+    games::{
+        board::{Board, GameResult},
+        connect4::{Connect4Board, Connect4Move, MAX_COLUMNS},
+    },
+};
+
+use super::{
+    c4_solver::{Connect4Solver, score_move},
+    search::{Search, SearchLimits, SearchResult},
+    tt::{TT, TTBound, decisive_score_from_tt, decisive_score_to_tt},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TTEntry {
+    mv: Option<Connect4Move>,
+    score: i16,
+    depth: u8,
+    bound: TTBound,
+}
+
+impl TTEntry {
+    fn adjust_from_tt(&mut self, ply: i32) {
+        if self.score.abs() as i32 > Connect4Eval::MAX_EVAL {
+            self.score = decisive_score_from_tt(self.score as i32, ply) as i16;
+        }
+    }
+
+    fn to_tt(&self, ply: i32) -> Self {
+        let mut result = *self;
+        if result.score.abs() as i32 > Connect4Eval::MAX_EVAL {
+            result.score = decisive_score_to_tt(self.score as i32, ply) as i16;
+        }
+        result
+    }
+}
+
+// Plays connect 4 under time limits with a heuristic evaluation,
+// switching to the exact solver once few enough squares are left
+pub struct Connect4Search {
+    nodes: u64,
+    root_best_move: Option<Connect4Move>,
+    root_depth: i32,
+    stop: bool,
+    start_time: Instant,
+    limits: SearchLimits,
+    tt: TT<TTEntry>,
+    eval: Connect4Eval,
+    solver: Connect4Solver,
+}
+
+impl Connect4Search {
+    const SCORE_WIN: i32 = <Connect4Solver>::SCORE_WIN;
+    const SOLVE_EMPTY_SQUARES: u32 = 20;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: 0,
+            root_best_move: None,
+            root_depth: 0,
+            stop: false,
+            start_time: Instant::now(),
+            limits: SearchLimits::default(),
+            tt: TT::new(16),
+            eval: Connect4Eval::default(),
+            solver: Connect4Solver::new(),
+        }
+    }
+
+    fn order_moves(
+        &self,
+        board: &Connect4Board,
+        moves: &mut ArrayVec<Connect4Move, MAX_COLUMNS>,
+        tt_move: Option<Connect4Move>,
+    ) {
+        moves.sort_by_key(|mv: &Connect4Move| {
+            if Some(*mv) == tt_move {
+                -1000000
+            } else {
+                -score_move(board, *mv)
+            }
+        });
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.root_depth <= 1 || self.nodes % 1024 != 0 {
+            return false;
+        }
+        if let Some(max_nodes) = self.limits.max_nodes {
+            if self.nodes >= max_nodes {
+                return true;
+            }
+        }
+        if let Some(max_time) = self.limits.max_time {
+            if Instant::now() - self.start_time > Duration::from_millis(max_time) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn alpha_beta<const PV: bool>(
+        &mut self,
+        board: &mut Connect4Board,
+        depth: i32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.should_stop() {
+            self.stop = true;
+            return 0;
+        }
+
+        let root = ply == 0;
+
+        match board.game_result() {
+            GameResult::WIN => return Self::SCORE_WIN - ply,
+            GameResult::DRAW => return 0,
+            GameResult::LOSS => return -Self::SCORE_WIN + ply,
+            _ => {}
+        }
+
+        // win on the next move, the root also needs the move itself
+        let move_locations = board.curr_state().move_locations();
+        let winning_moves = board.curr_state().our_threats() & move_locations;
+        if winning_moves.any() {
+            if root {
+                self.root_best_move = board
+                    .gen_moves()
+                    .into_iter()
+                    .find(|mv| winning_moves.has(mv.sq()));
+            }
+            return Self::SCORE_WIN - (ply + 1);
+        }
+        // cannot stop the opponent from winning in 2 moves
+        let opp_threats = board.curr_state().their_threats();
+        if !root
+            && ((opp_threats & move_locations).multiple()
+                || (opp_threats & opp_threats.south() & move_locations).any())
+        {
+            return -Self::SCORE_WIN + (ply + 2);
+        }
+
+        if depth <= 0 {
+            return self.eval.evaluate(board);
+        }
+
+        let mut non_losing_moves = if (opp_threats & move_locations).any() {
+            opp_threats & move_locations
+        } else {
+            move_locations & !opp_threats.south()
+        };
+        if non_losing_moves.empty() {
+            if !root {
+                // every move lets the opponent win immediately
+                return -Self::SCORE_WIN + (ply + 2);
+            }
+            non_losing_moves = move_locations;
+        }
+
+        let key = board.curr_state().key();
+        let tt_entry = self.tt.probe(key).map(|mut entry| {
+            entry.adjust_from_tt(ply);
+            entry
+        });
+
+        if !PV {
+            if let Some(entry) = tt_entry {
+                if entry.depth as i32 >= depth
+                    && (entry.bound == TTBound::EXACT
+                        || (entry.bound == TTBound::UPPER && entry.score as i32 <= alpha)
+                        || (entry.bound == TTBound::LOWER && entry.score as i32 >= beta))
+                {
+                    return entry.score as i32;
+                }
+            }
+        }
+
+        let mut moves = board.gen_moves();
+        self.order_moves(board, &mut moves, tt_entry.and_then(|tte| tte.mv));
+
+        let mut best_score = -Self::SCORE_WIN;
+        let mut best_move = None;
+        let mut tt_bound = TTBound::UPPER;
+        let mut moves_played = 0;
+
+        for mv in moves.iter() {
+            let mv = *mv;
+            if !non_losing_moves.has(mv.sq()) {
+                continue;
+            }
+            board.make_move(mv);
+            self.nodes += 1;
+            moves_played += 1;
+
+            let mut score = 0;
+            if !PV || moves_played > 1 {
+                score = -self.alpha_beta::<false>(board, depth - 1, ply + 1, -alpha - 1, -alpha);
+            }
+            if PV && (moves_played == 1 || score > alpha) {
+                score = -self.alpha_beta::<true>(board, depth - 1, ply + 1, -beta, -alpha);
+            }
+
+            board.unmake_move();
+            if self.stop {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                tt_bound = TTBound::EXACT;
+
+                if ply == 0 {
+                    self.root_best_move = Some(mv);
+                }
+            }
+
+            if score >= beta {
+                tt_bound = TTBound::LOWER;
+                break;
+            }
+        }
+
+        self.tt.store(
+            key,
+            TTEntry {
+                mv: best_move,
+                depth: depth as u8,
+                score: best_score as i16,
+                bound: tt_bound,
+            }
+            .to_tt(ply),
+        );
+
+        best_score
+    }
+}
+
+impl Search<Connect4Board> for Connect4Search {
+    fn search(
+        &mut self,
+        board: &Connect4Board,
+        limits: SearchLimits,
+    ) -> SearchResult<Connect4Board> {
+        let empty_squares = board.curr_state().empty_squares();
+        if empty_squares <= Self::SOLVE_EMPTY_SQUARES {
+            return self.solver.search(board, limits);
+        }
+
+        self.nodes = 0;
+        self.root_best_move = None;
+        self.stop = false;
+        let mut tmp_board = board.clone();
+
+        self.start_time = Instant::now();
+        // there is nothing to search once the game is over, so the null move is played
+        let score = match board.game_result() {
+            GameResult::WIN => Some(Self::SCORE_WIN),
+            GameResult::DRAW => Some(0),
+            GameResult::LOSS => Some(-Self::SCORE_WIN),
+            _ => None,
+        };
+        if let Some(score) = score {
+            return SearchResult {
+                nodes: 0,
+                time: Duration::ZERO,
+                best_move: Connect4Move::null(),
+                score,
+                pv: Vec::new(),
+            };
+        }
+        self.limits = limits;
+        let mut score = 0;
+        let mut max_depth = empty_squares as i32;
+        if let Some(max) = limits.max_depth {
+            max_depth = max_depth.min(max as i32);
+        }
+        let mut best_move = None;
+        for depth in 1..=max_depth {
+            self.root_depth = depth;
+            let iter_score = self.alpha_beta::<true>(
+                &mut tmp_board,
+                depth,
+                0,
+                -Self::SCORE_WIN,
+                Self::SCORE_WIN,
+            );
+            if self.stop {
+                break;
+            }
+
+            score = iter_score;
+            best_move = self.root_best_move;
+            let elapsed = Instant::now() - self.start_time;
+            println!(
+                "info depth {} nodes {} time {} score cp {} nps {} pv {}",
+                depth,
+                self.nodes,
+                elapsed.as_millis(),
+                score,
+                (self.nodes as f64 / elapsed.as_secs_f64()) as i32,
+                best_move.unwrap()
+            );
+
+            // the result is proven, searching deeper will not change it
+            if score.abs() > Connect4Eval::MAX_EVAL {
+                break;
+            }
+        }
+        let end_time = Instant::now();
+
+        SearchResult {
+            nodes: self.nodes,
+            time: end_time - self.start_time,
+            best_move: best_move.unwrap(),
+            score: score,
+            pv: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.tt.clear();
+        self.solver.clear();
+    }
+}
@@ -1,5 +1,10 @@
 pub mod ab_solver;
+pub mod ataxx;
+pub mod c4_book;
 pub mod c4_solver;
+pub mod connect4;
+pub mod retrograde;
 pub mod search;
 pub mod three_check;
+pub mod three_check_tb;
 pub mod tt;
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::time::Instant;
+
+use crate::games::board::{Board, BoardIndex, GameResult};
+
+use super::search::{Search, SearchLimits, SearchResult};
+
+const MAGIC: &[u8; 8] = b"calrtb\0\0";
+
+// entries keep the result in the top 2 bits and the distance to the end of the game in plies
+// in the rest. 0 is a position that can not be reached from the roots the table was built from
+const RESULT_SHIFT: u32 = 14;
+const MAX_DISTANCE: u16 = (1 << RESULT_SHIFT) - 1;
+const NO_SLOT: u32 = u32::MAX;
+
+pub(super) fn pack(result: GameResult, distance: u16) -> u16 {
+    let result = match result {
+        GameResult::WIN => 1,
+        GameResult::DRAW => 2,
+        GameResult::LOSS => 3,
+        GameResult::NONE => return 0,
+    };
+    (result << RESULT_SHIFT) | distance.min(MAX_DISTANCE)
+}
+
+pub(super) fn unpack(entry: u16) -> Option<(GameResult, u16)> {
+    let result = match entry >> RESULT_SHIFT {
+        1 => GameResult::WIN,
+        2 => GameResult::DRAW,
+        3 => GameResult::LOSS,
+        _ => return None,
+    };
+    Some((result, entry & MAX_DISTANCE))
+}
+
+// exact results for every position reachable from some roots, from the side to move's view
+#[derive(Debug, Clone)]
+pub struct RetrogradeTable {
+    entries: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetrogradeStats {
+    pub positions: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub max_distance: u16,
+}
+
+impl RetrogradeTable {
+    // walks forward from the roots to find every reachable position and its predecessors, then
+    // resolves results backwards from the finished games. wins take the shortest way to the end,
+    // losses the longest and anything left unresolved, including cycles, is a draw. draws do not
+    // get a distance
+    pub fn generate<B: Board, I: BoardIndex<B>>(index: &I, roots: &[B]) -> Self {
+        let mut slots = vec![NO_SLOT; index.size()];
+        let mut positions: Vec<usize> = Vec::new();
+        let mut results: Vec<GameResult> = Vec::new();
+        let mut edges: Vec<(u32, u32)> = Vec::new();
+
+        let mut queue = VecDeque::new();
+        for root in roots {
+            let root_index = index.index(root).unwrap();
+            if slots[root_index] == NO_SLOT {
+                slots[root_index] = positions.len() as u32;
+                positions.push(root_index);
+                results.push(root.game_result());
+                queue.push_back(root.clone());
+            }
+        }
+        while let Some(mut board) = queue.pop_front() {
+            let parent = slots[index.index(&board).unwrap()];
+            if results[parent as usize] != GameResult::NONE {
+                continue;
+            }
+            for mv in board.gen_moves() {
+                if !board.make_move(mv) {
+                    continue;
+                }
+                let child_index = index.index(&board).unwrap();
+                if slots[child_index] == NO_SLOT {
+                    slots[child_index] = positions.len() as u32;
+                    positions.push(child_index);
+                    results.push(board.game_result());
+                    queue.push_back(board.clone());
+                }
+                edges.push((slots[child_index], parent));
+                board.unmake_move();
+            }
+        }
+
+        // predecessors of each position next to each other
+        edges.sort_unstable();
+        let mut pred_start = vec![0usize; positions.len() + 1];
+        let mut moves_left = vec![0u32; positions.len()];
+        for &(child, parent) in edges.iter() {
+            pred_start[child as usize + 1] += 1;
+            moves_left[parent as usize] += 1;
+        }
+        for i in 0..positions.len() {
+            pred_start[i + 1] += pred_start[i];
+        }
+
+        let mut distances = vec![0u16; positions.len()];
+        let mut queue = VecDeque::new();
+        for (slot, result) in results.iter().enumerate() {
+            if *result == GameResult::WIN || *result == GameResult::LOSS {
+                queue.push_back(slot);
+            }
+        }
+        // the queue holds positions in order of distance, so the first win found for a position
+        // is the shortest and the child that makes it a loss is the longest
+        while let Some(child) = queue.pop_front() {
+            let child_result = results[child];
+            let distance = distances[child] + 1;
+            for &(_, parent) in edges[pred_start[child]..pred_start[child + 1]].iter() {
+                let parent = parent as usize;
+                if results[parent] != GameResult::NONE {
+                    continue;
+                }
+                if child_result == GameResult::LOSS {
+                    results[parent] = GameResult::WIN;
+                } else {
+                    moves_left[parent] -= 1;
+                    if moves_left[parent] > 0 {
+                        continue;
+                    }
+                    results[parent] = GameResult::LOSS;
+                }
+                distances[parent] = distance;
+                queue.push_back(parent);
+            }
+        }
+
+        let mut entries = vec![0; index.size()];
+        for (slot, position) in positions.iter().enumerate() {
+            let result = match results[slot] {
+                GameResult::NONE => GameResult::DRAW,
+                result => result,
+            };
+            entries[*position] = pack(result, distances[slot]);
+        }
+        Self { entries }
+    }
+
+    // for tables built some other way, entries are packed like generate packs them
+    pub(super) fn from_entries(entries: Vec<u16>) -> Self {
+        Self { entries }
+    }
+
+    pub(super) fn entry(&self, index: usize) -> Option<(GameResult, u16)> {
+        unpack(*self.entries.get(index)?)
+    }
+
+    pub fn probe<B: Board, I: BoardIndex<B>>(
+        &self,
+        index: &I,
+        board: &B,
+    ) -> Option<(GameResult, u16)> {
+        unpack(*self.entries.get(index.index(board)?)?)
+    }
+
+    pub fn stats(&self) -> RetrogradeStats {
+        let mut stats = RetrogradeStats::default();
+        for (result, distance) in self.entries.iter().filter_map(|entry| unpack(*entry)) {
+            stats.positions += 1;
+            match result {
+                GameResult::WIN => stats.wins += 1,
+                GameResult::DRAW => stats.draws += 1,
+                _ => stats.losses += 1,
+            }
+            stats.max_distance = stats.max_distance.max(distance);
+        }
+        stats
+    }
+
+    // the header is followed by the entries as little endian u16s
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(16 + 2 * self.entries.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.to_le_bytes());
+        }
+        File::create(filename)?.write_all(&bytes)
+    }
+
+    pub fn load(filename: &str) -> Option<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename).ok()?.read_to_end(&mut bytes).ok()?;
+        if bytes.len() < 16 || !bytes.starts_with(MAGIC) {
+            return None;
+        }
+        let len = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        if bytes.len() != 16 + 2 * len {
+            return None;
+        }
+        let entries = bytes[16..]
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        Some(Self { entries })
+    }
+}
+
+// plays straight from a table, scored like ABSolver so the two can be compared
+pub struct RetrogradeSearch<B: Board, I: BoardIndex<B>> {
+    table: RetrogradeTable,
+    index: I,
+    _board: PhantomData<B>,
+}
+
+impl<B: Board, I: BoardIndex<B>> RetrogradeSearch<B, I> {
+    const SCORE_WIN: i32 = 1000;
+
+    pub fn new(table: RetrogradeTable, index: I) -> Self {
+        Self {
+            table,
+            index,
+            _board: PhantomData,
+        }
+    }
+
+    pub fn index(&self) -> &I {
+        &self.index
+    }
+
+    pub fn score(&self, board: &B) -> Option<i32> {
+        Some(match self.table.probe(&self.index, board)? {
+            (GameResult::WIN, distance) => Self::SCORE_WIN - distance as i32,
+            (GameResult::LOSS, distance) => -Self::SCORE_WIN + distance as i32,
+            _ => 0,
+        })
+    }
+}
+
+impl<B: Board, I: BoardIndex<B>> Search<B> for RetrogradeSearch<B, I> {
+    fn search(&mut self, board: &B, _limits: SearchLimits) -> SearchResult<B> {
+        let start_time = Instant::now();
+        let mut tmp_board = board.clone();
+        let mut best = None;
+        let mut nodes = 0;
+        for mv in board.gen_moves() {
+            if !tmp_board.make_move(mv) {
+                continue;
+            }
+            nodes += 1;
+            // a move out of the table is never preferred to one with a known result
+            let score = self
+                .score(&tmp_board)
+                .map_or(-Self::SCORE_WIN - 1, |score| -score + score.signum());
+            tmp_board.unmake_move();
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((mv, score));
+            }
+        }
+        let (best_move, score) = best.unwrap();
+
+        SearchResult {
+            nodes,
+            time: Instant::now() - start_time,
+            best_move,
+            score,
+            pv: Vec::new(),
+        }
+    }
+}
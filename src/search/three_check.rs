@@ -3,14 +3,17 @@ use std::time::{Duration, Instant};
 use crate::{
     eval::{Eval, ThreeCheckEval},
     games::{
-        board::Board,
-        three_check::{Move, MoveList, PieceType, ThreeCheckBoard, see},
+        board::{Board, GameResult},
+        three_check::{
+            GenMode, Move, MoveKind, MoveList, PieceType, ThreeCheckBoard, Variant, see,
+        },
     },
 };
 
 use super::{
     search::{Search, SearchLimits, SearchResult},
-    tt::{TT, TTBound, decisive_score_from_tt},
+    three_check_tb::ThreeCheckTablebase,
+    tt::{TT, TTBound, decisive_score_from_tt, decisive_score_to_tt},
 };
 
 fn mvv_lva(captured: PieceType, moving: PieceType) -> i32 {
@@ -36,7 +39,7 @@ impl TTEntry {
     fn to_tt(&self, ply: i32) -> Self {
         let mut result = self.clone();
         if result.score.abs() as i32 >= ThreeCheckSearch::SCORE_WIN - 128 {
-            result.score = decisive_score_from_tt(self.score as i32, ply) as i16;
+            result.score = decisive_score_to_tt(self.score as i32, ply) as i16;
         }
         result
     }
@@ -50,7 +53,9 @@ pub struct ThreeCheckSearch {
     start_time: Instant,
     limits: SearchLimits,
     tt: TT<TTEntry>,
+    eval: ThreeCheckEval,
     history: [[[i32; 64]; 64]; 2],
+    tablebase: ThreeCheckTablebase,
 }
 
 impl ThreeCheckSearch {
@@ -65,10 +70,16 @@ impl ThreeCheckSearch {
             start_time: Instant::now(),
             limits: SearchLimits::default(),
             tt: TT::new(16),
+            eval: ThreeCheckEval::new(),
             history: [[[0; 64]; 64]; 2],
+            tablebase: ThreeCheckTablebase::new(),
         }
     }
 
+    pub fn set_tablebase(&mut self, tablebase: ThreeCheckTablebase) {
+        self.tablebase = tablebase;
+    }
+
     fn score_move(&mut self, board: &mut ThreeCheckBoard, mv: Move, tt_move: Option<Move>) -> i32 {
         if Some(mv) == tt_move {
             return 1000000;
@@ -92,7 +103,15 @@ impl ThreeCheckSearch {
         moves.sort_by_key(|mv: &Move| -self.score_move(board, *mv, tt_move));
     }
 
-    fn qsearch(&mut self, board: &mut ThreeCheckBoard, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+    // checks is set on the first ply only, later plies keep to noisy moves
+    fn qsearch(
+        &mut self,
+        board: &mut ThreeCheckBoard,
+        ply: i32,
+        checks: bool,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
         if let Some(max_time) = self.limits.max_time {
             if self.root_depth > 1
                 && self.nodes % 1024 == 0
@@ -103,7 +122,7 @@ impl ThreeCheckSearch {
             }
         }
 
-        if board.curr_state().check_count(board.curr_state().stm()) >= 3 {
+        if board.curr_state().variant_loss() {
             return -Self::SCORE_WIN + ply;
         }
 
@@ -111,7 +130,11 @@ impl ThreeCheckSearch {
             return 0;
         }
 
-        let static_eval = ThreeCheckEval::evaluate(board);
+        if board.curr_state().variant() == Variant::Antichess {
+            return self.antichess_qsearch(board, ply, alpha, beta);
+        }
+
+        let static_eval = self.eval.evaluate(board);
         if static_eval >= beta {
             return static_eval;
         }
@@ -120,12 +143,20 @@ impl ThreeCheckSearch {
             alpha = static_eval;
         }
 
-        let mut moves = board.gen_moves();
-        if moves.len() == 0 {
-            if board.curr_state().checkers().any() {
-                return -Self::SCORE_WIN + ply;
-            }
-            return 0;
+        // everything is generated in check so that mate can be detected, but only
+        // noisy moves are searched either way. with a check limit every check brings the
+        // side to move closer to winning, so quiet checks are searched on the first ply
+        let in_check = board.curr_state().checkers().any();
+        let mut moves = if in_check {
+            board.gen_moves()
+        } else {
+            board.curr_state().gen_moves_mode(GenMode::Noisy)
+        };
+        if in_check && moves.len() == 0 {
+            return -Self::SCORE_WIN + ply;
+        }
+        if checks && !in_check && board.curr_state().check_limit().is_some() {
+            moves.extend(board.curr_state().gen_moves_mode(GenMode::QuietChecks));
         }
         self.order_moves(board, &mut moves, None);
 
@@ -133,8 +164,7 @@ impl ThreeCheckSearch {
 
         for mv in moves.iter() {
             let mv = *mv;
-            let capture = board.piece_on(mv.to_sq()).is_some();
-            if !capture {
+            if in_check && !board.curr_state().is_noisy(mv) {
                 continue;
             }
 
@@ -145,7 +175,56 @@ impl ThreeCheckSearch {
             board.make_move(mv);
             self.nodes += 1;
 
-            let score = -self.qsearch(board, ply + 1, -beta, -alpha);
+            let score = -self.qsearch(board, ply + 1, false, -beta, -alpha);
+            board.unmake_move();
+
+            if self.stop {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+
+            if score >= beta {
+                break;
+            }
+        }
+
+        best_score
+    }
+
+    // captures are compulsory, so standing pat is only possible without one
+    // and every capture has to be searched since the alternatives are other captures
+    fn antichess_qsearch(
+        &mut self,
+        board: &mut ThreeCheckBoard,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        let mut moves = board.gen_moves();
+        if moves.len() == 0 {
+            return Self::SCORE_WIN - ply;
+        }
+        let forced =
+            moves[0].kind() == MoveKind::Enpassant || board.piece_on(moves[0].to_sq()).is_some();
+        if !forced {
+            return self.eval.evaluate(board);
+        }
+        self.order_moves(board, &mut moves, None);
+
+        let mut best_score = -Self::SCORE_WIN;
+
+        for mv in moves.iter() {
+            board.make_move(*mv);
+            self.nodes += 1;
+
+            let score = -self.qsearch(board, ply + 1, false, -beta, -alpha);
             board.unmake_move();
 
             if self.stop {
@@ -196,7 +275,7 @@ impl ThreeCheckSearch {
 
         let root = ply == 0;
 
-        if board.curr_state().check_count(board.curr_state().stm()) >= 3 {
+        if board.curr_state().variant_loss() {
             return -Self::SCORE_WIN + ply;
         }
 
@@ -204,13 +283,33 @@ impl ThreeCheckSearch {
             return 0;
         }
 
+        // exact results for small endgames, the root still searches to pick a move
+        if !root && let Some((result, distance)) = self.tablebase.probe(board.curr_state()) {
+            return match result {
+                GameResult::WIN => Self::SCORE_WIN - ply - distance as i32,
+                GameResult::LOSS => -Self::SCORE_WIN + ply + distance as i32,
+                _ => 0,
+            };
+        }
+
         let in_check = board.curr_state().checkers().any();
+        // forced captures make zugzwang the norm and null moves illegal in antichess
+        let antichess = board.curr_state().variant() == Variant::Antichess;
 
-        let tt_entry = self.tt.probe(board.curr_state().zkey().value());
+        let tt_entry = self
+            .tt
+            .probe(board.curr_state().zkey().value())
+            .map(|mut entry| {
+                entry.adjust_from_tt(ply);
+                entry
+            });
 
         if !PV {
             if let Some(entry) = tt_entry {
-                if entry.depth as i32 >= depth
+                // antichess never prunes moves, so like in the solvers a decisive score is proven
+                // and holds at any depth
+                let proven = antichess && entry.score.abs() as i32 >= Self::SCORE_WIN - 128;
+                if (entry.depth as i32 >= depth || proven)
                     && (entry.bound == TTBound::EXACT
                         || (entry.bound == TTBound::UPPER && entry.score as i32 <= alpha)
                         || (entry.bound == TTBound::LOWER && entry.score as i32 >= beta))
@@ -221,12 +320,12 @@ impl ThreeCheckSearch {
         }
 
         if depth <= 0 {
-            return self.qsearch(board, ply, alpha, beta);
+            return self.qsearch(board, ply, true, alpha, beta);
         }
 
-        let static_eval = ThreeCheckEval::evaluate(board);
+        let static_eval = self.eval.evaluate(board);
 
-        if !in_check && !PV {
+        if !in_check && !PV && !antichess {
             if depth <= 4 && static_eval - 100 * depth >= beta {
                 return static_eval;
             }
@@ -243,39 +342,55 @@ impl ThreeCheckSearch {
             }
         }
 
-        let mut moves = board.gen_moves();
-        if moves.len() == 0 {
-            if board.curr_state().checkers().any() {
-                return -Self::SCORE_WIN + ply;
-            }
-            return 0;
+        // moves come in stages, a legal tt move first, then noisy moves and then quiets,
+        // so a cutoff early on skips generating and ordering the rest
+        let tt_move = tt_entry
+            .and_then(|tte| tte.mv)
+            .filter(|mv| board.curr_state().is_legal(*mv));
+        let mut moves = MoveList::new();
+        let mut stages = [GenMode::Noisy, GenMode::Quiet].into_iter();
+        if let Some(mv) = tt_move {
+            moves.push(mv);
         }
 
-        self.order_moves(board, &mut moves, tt_entry.and_then(|tte| tte.mv));
         let mut best_score = -Self::SCORE_WIN;
         let mut best_move = None;
         let mut tt_bound = TTBound::UPPER;
         let mut moves_played = 0;
 
-        for mv in moves.iter() {
-            let mv = *mv;
+        let mut idx = 0;
+        loop {
+            while idx == moves.len() {
+                let Some(mode) = stages.next() else {
+                    break;
+                };
+                let mut stage = board.curr_state().gen_moves_mode(mode);
+                stage.retain(|mv| Some(*mv) != tt_move);
+                self.order_moves(board, &mut stage, None);
+                moves.extend(stage);
+            }
+            if idx == moves.len() {
+                break;
+            }
+            let mv = moves[idx];
+            idx += 1;
+
             let capture = board.piece_on(mv.to_sq()).is_some();
             let see_prune = !root && best_score > -Self::SCORE_WIN + 128 && !see::see(board.curr_state(), mv, -150 * depth);
-            // three_check uses legal movegen
-            board.make_move(mv);
-            let gives_check = board.curr_state().checkers().any();
+            let gives_check = board.curr_state().gives_check(mv);
 
-            if !root && best_score > -Self::SCORE_WIN + 128 && !gives_check {
+            if !root && !antichess && best_score > -Self::SCORE_WIN + 128 && !gives_check {
                 if !in_check && see_prune {
-                    board.unmake_move();
                     continue;
                 }
                 if !in_check && !capture && depth <= 4 && static_eval + 100 + 150 * depth <= alpha {
-                    board.unmake_move();
                     continue;
                 }
             }
 
+            // three_check uses legal movegen
+            board.make_move(mv);
+
             self.nodes += 1;
             moves_played += 1;
 
@@ -333,6 +448,16 @@ impl ThreeCheckSearch {
             }
         }
 
+        if moves.len() == 0 {
+            if antichess {
+                return Self::SCORE_WIN - ply;
+            }
+            if in_check {
+                return -Self::SCORE_WIN + ply;
+            }
+            return 0;
+        }
+
         self.tt.store(
             board.curr_state().zkey().value(),
             TTEntry {
@@ -340,7 +465,8 @@ impl ThreeCheckSearch {
                 depth: depth as u8,
                 score: best_score as i16,
                 bound: tt_bound,
-            },
+            }
+            .to_tt(ply),
         );
 
         best_score
@@ -374,6 +500,7 @@ impl ThreeCheckSearch {
 
     pub fn clear(&mut self) {
         self.tt.clear();
+        self.eval.clear();
     }
 }
 
@@ -414,7 +541,7 @@ impl Search<ThreeCheckBoard> for ThreeCheckSearch {
                 elapsed.as_millis(),
                 score,
                 (self.nodes as f64 / elapsed.as_secs_f64()) as i32,
-                best_move.unwrap()
+                best_move.unwrap().to_uci(board.curr_state().is_frc())
             );
         }
         let end_time = Instant::now();
@@ -0,0 +1,630 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::games::board::GameResult;
+use crate::games::three_check::{
+    Color, Move, MoveKind, Piece, PieceType, Square, ThreeCheckState, Variant, attacks,
+};
+
+use super::retrograde::{RetrogradeStats, RetrogradeTable, pack};
+
+const PIECE_ORDER: [PieceType; 5] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
+
+const INVALID: u8 = u8::MAX;
+const CANNOT_LOSE: u16 = u16::MAX;
+
+fn piece_letter(pt: PieceType) -> char {
+    Piece::new(Color::White, pt).char_repr()
+}
+
+// whether the side that did not push could take en passant on ep
+fn en_passant_possible(state: &ThreeCheckState, ep: Square, pusher: Color) -> bool {
+    let pawns = state.colored_pieces(Piece::new(pusher.flip(), PieceType::Pawn));
+    (attacks::pawn_attacks(pusher, ep) & pawns).any()
+}
+
+// moves that stay within the same table, double pushes are left out when they leave an en
+// passant capture since tables have no en passant squares
+fn is_internal(state: &ThreeCheckState, mv: Move) -> bool {
+    if state.is_capture(mv) || mv.kind() != MoveKind::None {
+        return false;
+    }
+    let (from, to) = (mv.from_sq().value(), mv.to_sq().value());
+    let pawn = state.piece_at(mv.from_sq()).unwrap().piece_type() == PieceType::Pawn;
+    !(pawn
+        && from.abs_diff(to) == 16
+        && en_passant_possible(state, Square::from_raw((from + to) / 2), state.stm()))
+}
+
+// the pieces of each side besides the king, strongest first
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Material {
+    pieces: [Vec<PieceType>; 2],
+}
+
+impl Material {
+    fn new(mut white: Vec<PieceType>, mut black: Vec<PieceType>) -> Self {
+        white.sort_by_key(|pt| std::cmp::Reverse(*pt as u8));
+        black.sort_by_key(|pt| std::cmp::Reverse(*pt as u8));
+        Self {
+            pieces: [white, black],
+        }
+    }
+
+    fn of(state: &ThreeCheckState) -> Self {
+        let mut pieces = [Vec::new(), Vec::new()];
+        for sq in 0..64 {
+            if let Some(piece) = state.piece_at(Square::from_raw(sq))
+                && piece.piece_type() != PieceType::King
+            {
+                pieces[piece.color() as usize].push(piece.piece_type());
+            }
+        }
+        let [white, black] = pieces;
+        Self::new(white, black)
+    }
+
+    // "KQvK" or "KRPvKR"
+    pub fn parse(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let parse_side = |side: &str| -> Option<Vec<PieceType>> {
+            let mut chars = side.chars();
+            if chars.next()? != 'K' {
+                return None;
+            }
+            chars
+                .map(|c| PIECE_ORDER.into_iter().find(|pt| piece_letter(*pt) == c))
+                .collect()
+        };
+        Some(Self::new(parse_side(white)?, parse_side(black)?))
+    }
+
+    pub fn piece_count(&self) -> usize {
+        2 + self.pieces[0].len() + self.pieces[1].len()
+    }
+
+    fn side_key(&self, c: Color) -> (usize, Vec<u8>) {
+        let pieces = &self.pieces[c as usize];
+        (pieces.len(), pieces.iter().map(|pt| *pt as u8).collect())
+    }
+
+    // tables are only built with white as the stronger side, black being stronger means
+    // looking the position up with the colors swapped
+    fn needs_flip(&self) -> bool {
+        self.side_key(Color::White) < self.side_key(Color::Black)
+    }
+
+    fn flipped(&self) -> Self {
+        Self::new(self.pieces[1].clone(), self.pieces[0].clone())
+    }
+
+    fn canonical(&self) -> Self {
+        if self.needs_flip() {
+            self.flipped()
+        } else {
+            self.clone()
+        }
+    }
+
+    // the tables a capture or promotion can lead to, without the bare kings
+    fn successors(&self) -> Vec<Self> {
+        let mut result = Vec::new();
+        for side in 0..2 {
+            for i in 0..self.pieces[side].len() {
+                let mut pieces = self.pieces.clone();
+                let removed = pieces[side].remove(i);
+                if pieces[0].len() + pieces[1].len() > 0 {
+                    result.push(Self::new(pieces[0].clone(), pieces[1].clone()));
+                }
+                if removed == PieceType::Pawn {
+                    for promo in &PIECE_ORDER[..4] {
+                        let mut pieces = pieces.clone();
+                        pieces[side].push(*promo);
+                        result.push(Self::new(pieces[0].clone(), pieces[1].clone()));
+                    }
+                }
+            }
+        }
+        result
+            .into_iter()
+            .map(|material| material.canonical())
+            .collect()
+    }
+
+    // every material with up to max_pieces pieces including the kings, one table each
+    pub fn all(max_pieces: usize) -> Vec<Self> {
+        let mut result: Vec<Self> = Vec::new();
+        let mut sets = vec![Vec::new()];
+        for _ in 2..max_pieces {
+            let mut next = Vec::new();
+            for set in sets.iter() {
+                for pt in PIECE_ORDER {
+                    for side in 0..2 {
+                        let mut set: Vec<(usize, PieceType)> = set.clone();
+                        set.push((side, pt));
+                        next.push(set);
+                    }
+                }
+            }
+            for set in next.iter() {
+                let side = |c: usize| set.iter().filter(|p| p.0 == c).map(|p| p.1).collect();
+                let material = Self::new(side(0), side(1)).canonical();
+                if !result.contains(&material) {
+                    result.push(material);
+                }
+            }
+            sets = next;
+        }
+        result.sort_by_key(|material| material.piece_count());
+        result
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, side) in self.pieces.iter().enumerate() {
+            if i == 1 {
+                write!(f, "v")?;
+            }
+            write!(f, "K")?;
+            for pt in side.iter() {
+                write!(f, "{}", piece_letter(*pt))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// white king, black king, then the other pieces in material order, 6 bits each. the white king
+// is kept on files a to d by mirroring, then come the side to move and both check counts
+struct EndgameIndex {
+    material: Material,
+    // no check limit is stored as 0, and counts are left out of the index
+    check_limit: u8,
+}
+
+impl EndgameIndex {
+    fn counts(&self) -> usize {
+        self.check_limit.max(1) as usize
+    }
+
+    fn size(&self) -> usize {
+        32 * 64usize.pow(self.material.piece_count() as u32 - 1) * 2 * self.counts().pow(2)
+    }
+
+    fn pieces(&self) -> Vec<Piece> {
+        let mut pieces = vec![
+            Piece::new(Color::White, PieceType::King),
+            Piece::new(Color::Black, PieceType::King),
+        ];
+        for (side, c) in [Color::White, Color::Black].into_iter().enumerate() {
+            for pt in self.material.pieces[side].iter() {
+                pieces.push(Piece::new(c, *pt));
+            }
+        }
+        pieces
+    }
+
+    // squares in the order of pieces(), with the colors swapped first if flip is set
+    fn placement(&self, state: &ThreeCheckState, flip: bool) -> Vec<Square> {
+        let pieces = self.pieces();
+        let mut squares = vec![None; pieces.len()];
+        for sq in 0..64 {
+            let sq = Square::from_raw(sq);
+            let Some(mut piece) = state.piece_at(sq) else {
+                continue;
+            };
+            let mut sq = sq;
+            if flip {
+                piece = Piece::new(piece.color().flip(), piece.piece_type());
+                sq = Square::from_raw(sq.value() ^ 56);
+            }
+            let slot = (0..pieces.len())
+                .find(|i| pieces[*i] == piece && squares[*i].is_none())
+                .unwrap();
+            squares[slot] = Some(sq);
+        }
+        let mut squares: Vec<Square> = squares.into_iter().map(Option::unwrap).collect();
+        if squares[0].file() >= 4 {
+            for sq in squares.iter_mut() {
+                *sq = Square::from_raw(sq.value() ^ 7);
+            }
+        }
+        // identical pieces are kept in square order so each position has one index
+        for i in 1..pieces.len() {
+            let mut j = i;
+            while j > 1 && pieces[j - 1] == pieces[j] && squares[j - 1] > squares[j] {
+                squares.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        squares
+    }
+
+    fn index(&self, state: &ThreeCheckState, flip: bool) -> Option<usize> {
+        let mut stm = state.stm();
+        let mut counts = [
+            state.check_count(Color::White),
+            state.check_count(Color::Black),
+        ];
+        if flip {
+            stm = stm.flip();
+            counts.swap(0, 1);
+        }
+        if self.check_limit == 0 {
+            counts = [0, 0];
+        } else if counts.iter().any(|count| *count >= self.check_limit) {
+            return None;
+        }
+
+        let squares = self.placement(state, flip);
+        let wk = squares[0];
+        let mut index = (wk.rank() * 4 + wk.file()) as usize;
+        for sq in squares[1..].iter() {
+            index = 64 * index + sq.value() as usize;
+        }
+        index = 2 * index + stm as usize;
+        index = self.counts() * index + counts[0] as usize;
+        Some(self.counts() * index + counts[1] as usize)
+    }
+
+    // None for indices that do not stand for a legal position
+    fn position(&self, mut index: usize) -> Option<ThreeCheckState> {
+        let black_count = (index % self.counts()) as u8;
+        index /= self.counts();
+        let white_count = (index % self.counts()) as u8;
+        index /= self.counts();
+        let stm = Color::from_raw((index % 2) as u8);
+        index /= 2;
+
+        let pieces = self.pieces();
+        let mut squares = vec![Square::from_raw(0); pieces.len()];
+        for i in (1..pieces.len()).rev() {
+            squares[i] = Square::from_raw((index % 64) as u16);
+            index /= 64;
+        }
+        squares[0] = Square::from_rank_file((index / 4) as u8, (index % 4) as u8);
+
+        for i in 1..pieces.len() {
+            if pieces[i - 1] == pieces[i] && squares[i - 1] >= squares[i] {
+                return None;
+            }
+            if pieces[i].piece_type() == PieceType::Pawn
+                && (squares[i].rank() == 0 || squares[i].rank() == 7)
+            {
+                return None;
+            }
+        }
+
+        let placement: Vec<(Square, Piece)> = squares.into_iter().zip(pieces).collect();
+        let check_limit = if self.check_limit == 0 {
+            None
+        } else {
+            Some(self.check_limit)
+        };
+        let state = ThreeCheckState::from_placement(
+            &placement,
+            stm,
+            [white_count, black_count],
+            check_limit,
+        )?;
+        // the side that just moved can not be in check
+        if state.attackers_to(state.king_sq(stm.flip()), stm).any() {
+            return None;
+        }
+        Some(state)
+    }
+
+    // indices of the positions that reach state with a move that stays in the table
+    fn unmoves(&self, state: &ThreeCheckState) -> Vec<usize> {
+        let stm = state.stm();
+        let mover = stm.flip();
+        let mut counts = [
+            state.check_count(Color::White),
+            state.check_count(Color::Black),
+        ];
+        // the move taken back gave the check
+        if self.check_limit != 0 && state.checkers().any() {
+            if counts[stm as usize] == 0 {
+                return Vec::new();
+            }
+            counts[stm as usize] -= 1;
+        }
+
+        let occ = state.occ();
+        let mut placement: Vec<(Square, Piece)> = (0..64)
+            .map(Square::from_raw)
+            .filter_map(|sq| Some((sq, state.piece_at(sq)?)))
+            .collect();
+        let mut result = Vec::new();
+        for slot in 0..placement.len() {
+            let (to, piece) = placement[slot];
+            if piece.color() != mover {
+                continue;
+            }
+            let mut froms = Vec::new();
+            if piece.piece_type() == PieceType::Pawn {
+                let back = |sq: Square| match mover {
+                    Color::White => Square::from_raw(sq.value() - 8),
+                    Color::Black => Square::from_raw(sq.value() + 8),
+                };
+                let single = back(to);
+                let start_rank = if mover == Color::White { 1 } else { 6 };
+                if !occ.has(single) && single.rank() != 0 && single.rank() != 7 {
+                    froms.push(single);
+                    let double = back(single);
+                    if double.rank() == start_rank
+                        && !occ.has(double)
+                        && !en_passant_possible(state, single, mover)
+                    {
+                        froms.push(double);
+                    }
+                }
+            } else {
+                let mut bb = attacks::piece_attacks(piece.piece_type(), mover, to, occ) & !occ;
+                while bb.any() {
+                    froms.push(bb.poplsb());
+                }
+            }
+
+            for from in froms {
+                placement[slot].0 = from;
+                let check_limit = if self.check_limit == 0 {
+                    None
+                } else {
+                    Some(self.check_limit)
+                };
+                if let Some(parent) =
+                    ThreeCheckState::from_placement(&placement, mover, counts, check_limit)
+                    && !parent.attackers_to(parent.king_sq(stm), mover).any()
+                {
+                    result.push(self.index(&parent, false).unwrap());
+                }
+            }
+            placement[slot].0 = to;
+        }
+        result
+    }
+}
+
+struct EndgameTable {
+    index: EndgameIndex,
+    table: RetrogradeTable,
+}
+
+// win, draw or loss for the side to move plus the distance to the end of the game in plies,
+// for standard rules positions with few pieces and any check counts. the 50 move rule is
+// ignored and positions with castling rights or a possible en passant capture are not covered,
+// so a double push that allows one never counts towards a win or loss while generating
+#[derive(Default)]
+pub struct ThreeCheckTablebase {
+    tables: HashMap<(Material, u8), EndgameTable>,
+    max_pieces: usize,
+}
+
+impl ThreeCheckTablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn stats(&self, material: &Material, check_limit: u8) -> Option<RetrogradeStats> {
+        let table = self.tables.get(&(material.canonical(), check_limit))?;
+        Some(table.table.stats())
+    }
+
+    fn file_name(material: &Material, check_limit: u8) -> String {
+        format!("{}-{}.ctb", material, check_limit)
+    }
+
+    // loads every table in dir, names look like KQvK-3.ctb with 0 for no check limit
+    pub fn load_dir(dir: &str) -> Self {
+        let mut result = Self::new();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return result;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some((material, limit)) = name
+                .strip_suffix(".ctb")
+                .and_then(|name| name.split_once('-'))
+            else {
+                continue;
+            };
+            let (Some(material), Ok(check_limit)) = (Material::parse(material), limit.parse())
+            else {
+                continue;
+            };
+            let path = entry.path();
+            let Some(table) = RetrogradeTable::load(path.to_str().unwrap()) else {
+                continue;
+            };
+            result.insert(material, check_limit, table);
+        }
+        result
+    }
+
+    fn insert(&mut self, material: Material, check_limit: u8, table: RetrogradeTable) {
+        self.max_pieces = self.max_pieces.max(material.piece_count());
+        let index = EndgameIndex {
+            material: material.clone(),
+            check_limit,
+        };
+        self.tables
+            .insert((material, check_limit), EndgameTable { index, table });
+    }
+
+    pub fn save_dir(&self, dir: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for ((material, check_limit), table) in self.tables.iter() {
+            let path = Path::new(dir).join(Self::file_name(material, *check_limit));
+            table.table.save(path.to_str().unwrap())?;
+        }
+        Ok(())
+    }
+
+    fn probe_material(
+        &self,
+        state: &ThreeCheckState,
+        material: &Material,
+        check_limit: u8,
+    ) -> Option<(GameResult, u16)> {
+        if material.pieces[0].is_empty() && material.pieces[1].is_empty() {
+            // two bare kings can never check or mate
+            return Some((GameResult::DRAW, 0));
+        }
+        let flip = material.needs_flip();
+        let table = self.tables.get(&(material.canonical(), check_limit))?;
+        table.table.entry(table.index.index(state, flip)?)
+    }
+
+    pub fn probe(&self, state: &ThreeCheckState) -> Option<(GameResult, u16)> {
+        if state.variant() != Variant::Standard
+            || state
+                .ep_square()
+                .is_some_and(|ep| en_passant_possible(state, ep, state.stm().flip()))
+            || state.castling_rooks().right_bits() != 0
+            || state.occ().popcount() as usize > self.max_pieces
+        {
+            return None;
+        }
+        let check_limit = state.check_limit().unwrap_or(0);
+        self.probe_material(state, &Material::of(state), check_limit)
+    }
+
+    // builds the table for material and any table a capture or promotion can reach first
+    pub fn generate(&mut self, material: &Material, check_limit: u8) {
+        let material = material.canonical();
+        if self.tables.contains_key(&(material.clone(), check_limit)) {
+            return;
+        }
+        for successor in material.successors() {
+            self.generate(&successor, check_limit);
+        }
+        let table = self.generate_table(&material, check_limit);
+        self.insert(material, check_limit, table);
+    }
+
+    // resolves positions backwards from the finished games in order of distance like
+    // RetrogradeTable::generate, but finds predecessors by taking moves back instead of storing
+    // every edge. moves that capture or promote are looked up in the smaller tables
+    fn generate_table(&self, material: &Material, check_limit: u8) -> RetrogradeTable {
+        let index = EndgameIndex {
+            material: material.clone(),
+            check_limit,
+        };
+        let size = index.size();
+        let mut entries = vec![0u16; size];
+        // moves whose result is still unknown, INVALID for indices that are not positions
+        let mut moves_left = vec![INVALID; size];
+        // the longest win among the moves resolved by other tables, CANNOT_LOSE once one of
+        // them does not win
+        let mut longest = vec![0u16; size];
+        // positions by distance, wins always end an odd number of plies away and losses an
+        // even number so the result does not need storing
+        let mut buckets: Vec<Vec<u32>> = Vec::new();
+        let push = |buckets: &mut Vec<Vec<u32>>, distance: u16, i| {
+            let distance = distance as usize;
+            if buckets.len() <= distance {
+                buckets.resize_with(distance + 1, Vec::new);
+            }
+            buckets[distance].push(i as u32);
+        };
+
+        for i in 0..size {
+            let Some(state) = index.position(i) else {
+                continue;
+            };
+            let moves = state.gen_moves();
+            moves_left[i] = 0;
+            if moves.is_empty() {
+                if state.checkers().any() {
+                    push(&mut buckets, 0, i);
+                } else {
+                    entries[i] = pack(GameResult::DRAW, 0);
+                }
+                continue;
+            }
+            for mv in moves {
+                let result = if state.gives_check(mv)
+                    && state.checks_remaining(state.stm().flip()) == Some(1)
+                {
+                    Some((GameResult::LOSS, 0))
+                } else if is_internal(&state, mv) {
+                    moves_left[i] += 1;
+                    continue;
+                } else if state.is_capture(mv) || mv.kind() == MoveKind::Promotion {
+                    let mut child = state.clone();
+                    child.make_move(mv);
+                    self.probe_material(&child, &Material::of(&child), check_limit)
+                } else {
+                    // a double push that allows en passant, which no table covers
+                    None
+                };
+                match result {
+                    Some((GameResult::LOSS, distance)) => {
+                        push(&mut buckets, distance + 1, i);
+                        longest[i] = CANNOT_LOSE;
+                    }
+                    Some((GameResult::WIN, distance)) if longest[i] != CANNOT_LOSE => {
+                        longest[i] = longest[i].max(distance + 1)
+                    }
+                    _ => longest[i] = CANNOT_LOSE,
+                }
+            }
+            if moves_left[i] == 0 && longest[i] != CANNOT_LOSE {
+                push(&mut buckets, longest[i], i);
+            }
+        }
+
+        let mut distance = 0;
+        while distance < buckets.len() {
+            let result = if distance % 2 == 1 {
+                GameResult::WIN
+            } else {
+                GameResult::LOSS
+            };
+            for i in std::mem::take(&mut buckets[distance]) {
+                let i = i as usize;
+                if entries[i] != 0 {
+                    continue;
+                }
+                entries[i] = pack(result, distance as u16);
+                let child = index.position(i).unwrap();
+                for parent in index.unmoves(&child) {
+                    if entries[parent] != 0 {
+                        continue;
+                    }
+                    if result == GameResult::LOSS {
+                        push(&mut buckets, distance as u16 + 1, parent);
+                        continue;
+                    }
+                    moves_left[parent] -= 1;
+                    if moves_left[parent] == 0 && longest[parent] != CANNOT_LOSE {
+                        let loss_distance = longest[parent].max(distance as u16 + 1);
+                        push(&mut buckets, loss_distance, parent);
+                    }
+                }
+            }
+            distance += 1;
+        }
+
+        // whatever is left can never be forced either way
+        for i in 0..size {
+            if moves_left[i] != INVALID && entries[i] == 0 {
+                entries[i] = pack(GameResult::DRAW, 0);
+            }
+        }
+        RetrogradeTable::from_entries(entries)
+    }
+}
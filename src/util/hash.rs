@@ -9,5 +9,8 @@ pub const fn murmur_hash3(mut key: u64) -> u64 {
 
 // based on boost hash combine from here: https://stackoverflow.com/a/27952689
 pub const fn hash_combine(lhs: u64, rhs: u64) -> u64 {
-    lhs ^ (rhs + 0x517cc1b727220a95 + (lhs << 6) + (lhs >> 2))
+    lhs ^ rhs
+        .wrapping_add(0x517cc1b727220a95)
+        .wrapping_add(lhs << 6)
+        .wrapping_add(lhs >> 2)
 }